@@ -3,8 +3,13 @@ use std::fs::create_dir_all;
 
 use cosmwasm_schema::{export_schema, remove_schemas, schema_for};
 
-use staking::msg::{ ConfigResponse, ExecuteMsg, InstantiateMsg, QueryMsg, UserResponse};
-use staking::state::Config;
+use staking::msg::{
+    AllStakersResponse, AprResponse, CampaignsResponse, ConfigResponse, ExecuteMsg, InstantiateMsg,
+    MigrateMsg, PauseFlagsResponse, QueryMsg, RewardReserveResponse, RewardsResponse,
+    SimulateWithdrawResponse, UnbondingResponse, UndistributedResponse, UserResponse,
+    ValidatorsResponse,
+};
+use staking::state::{Config, Ownership};
 
 fn main() {
     let mut out_dir = current_dir().unwrap();
@@ -14,8 +19,20 @@ fn main() {
 
     export_schema(&schema_for!(InstantiateMsg), &out_dir);
     export_schema(&schema_for!(ExecuteMsg), &out_dir);
+    export_schema(&schema_for!(MigrateMsg), &out_dir);
     export_schema(&schema_for!(QueryMsg), &out_dir);
     export_schema(&schema_for!(Config), &out_dir);
     export_schema(&schema_for!(UserResponse), &out_dir);
     export_schema(&schema_for!(ConfigResponse), &out_dir);
-}
\ No newline at end of file
+    export_schema(&schema_for!(UndistributedResponse), &out_dir);
+    export_schema(&schema_for!(Ownership), &out_dir);
+    export_schema(&schema_for!(UnbondingResponse), &out_dir);
+    export_schema(&schema_for!(RewardsResponse), &out_dir);
+    export_schema(&schema_for!(RewardReserveResponse), &out_dir);
+    export_schema(&schema_for!(CampaignsResponse), &out_dir);
+    export_schema(&schema_for!(ValidatorsResponse), &out_dir);
+    export_schema(&schema_for!(SimulateWithdrawResponse), &out_dir);
+    export_schema(&schema_for!(PauseFlagsResponse), &out_dir);
+    export_schema(&schema_for!(AllStakersResponse), &out_dir);
+    export_schema(&schema_for!(AprResponse), &out_dir);
+}
@@ -0,0 +1,66 @@
+use std::fmt;
+
+use cosmwasm_std::{
+    to_json_binary, Addr, Api, BankMsg, Coin, CosmosMsg, StdResult, Uint128, WasmMsg,
+};
+use cw20::Cw20ExecuteMsg;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// A token the contract can hold: a native bank denom or a CW20 contract.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AssetInfo {
+    Native { denom: String },
+    Cw20 { contract_addr: Addr },
+}
+
+impl AssetInfo {
+    pub fn native(denom: impl Into<String>) -> Self {
+        AssetInfo::Native {
+            denom: denom.into(),
+        }
+    }
+
+    /// Validates the CW20 contract address of an asset received in a message.
+    pub fn validate(self, api: &dyn Api) -> StdResult<Self> {
+        match self {
+            AssetInfo::Native { denom } => Ok(AssetInfo::Native { denom }),
+            AssetInfo::Cw20 { contract_addr } => Ok(AssetInfo::Cw20 {
+                contract_addr: api.addr_validate(contract_addr.as_str())?,
+            }),
+        }
+    }
+
+    /// Message sending `amount` of this asset from the contract to `recipient`.
+    pub fn transfer_msg(&self, recipient: &Addr, amount: Uint128) -> StdResult<CosmosMsg> {
+        Ok(match self {
+            AssetInfo::Native { denom } => BankMsg::Send {
+                to_address: recipient.to_string(),
+                amount: vec![Coin {
+                    denom: denom.clone(),
+                    amount,
+                }],
+            }
+            .into(),
+            AssetInfo::Cw20 { contract_addr } => WasmMsg::Execute {
+                contract_addr: contract_addr.to_string(),
+                msg: to_json_binary(&Cw20ExecuteMsg::Transfer {
+                    recipient: recipient.to_string(),
+                    amount,
+                })?,
+                funds: vec![],
+            }
+            .into(),
+        })
+    }
+}
+
+impl fmt::Display for AssetInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AssetInfo::Native { denom } => write!(f, "{}", denom),
+            AssetInfo::Cw20 { contract_addr } => write!(f, "{}", contract_addr),
+        }
+    }
+}
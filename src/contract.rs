@@ -1,41 +1,88 @@
 #[cfg(not(feature = "library"))]
 use cosmwasm_std::entry_point;
 use cosmwasm_std::{
-    to_json_binary, Binary, Coin, DepsMut, Env, MessageInfo, Response, StdResult, Uint128,
+    coin, from_json, to_json_binary, Addr, Binary, Decimal, Decimal256, Deps, DepsMut, DistributionMsg,
+    Env, MessageInfo, Order, Reply, Response, StakingMsg, StdError, StdResult, Storage, SubMsg,
+    Uint128, Uint256, Uint64,
 };
+use cw2::{set_contract_version, CONTRACT};
+use cw_storage_plus::Bound;
+use cw20::Cw20ReceiveMsg;
+use semver::Version;
 
+use crate::asset::AssetInfo;
 use crate::error::ContractError;
-use crate::msg::{ExecuteMsg, InstantiateMsg, QueryMsg, UserResponse, ConfigResponse};
-use crate::state::{Config, User, USERS, read_config, save_config};
+use crate::migrations::migrate_state;
+use crate::msg::{
+    AllStakersResponse, AprResponse, CampaignInfo, CampaignsResponse, ConfigResponse, ExecuteMsg,
+    InstantiateMsg, MigrateMsg, OwnershipAction, PauseFlagsResponse, QueryMsg, ReceiveMsg,
+    RewardAmount, RewardReserveResponse, RewardsResponse, SimulateWithdrawResponse, StakerInfo,
+    StreamApr, StreamReserve, UnbondingResponse, UndistributedResponse, UpdateConfigMsg, UserResponse,
+    ValidatorAllocation, ValidatorsResponse,
+};
+use crate::state::{
+    read_config, save_config, Campaign, Config, Deposit, EarlyWithdrawalPenalty, Lock, LockExpiry,
+    LockTier, Ownership, PauseFlags, RewardStream, UnbondingEntry, UndistributedPolicy, User, UserReward,
+    HARVEST_BALANCE, LOCK_EXPIRIES, OWNERSHIP, PAST_CAMPAIGNS, UNBONDING, USERS, VALIDATORS,
+};
+
+// version info for migration info
+const CONTRACT_NAME: &str = "crates.io:staking";
+const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
 
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn instantiate(
     deps: DepsMut,
     env: Env,
-    _info: MessageInfo,
+    info: MessageInfo,
     msg: InstantiateMsg,
-) -> StdResult<Response> {
+) -> Result<Response, ContractError> {
+    set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+    let owner = match msg.admin {
+        Some(admin) => deps.api.addr_validate(&admin)?,
+        None => info.sender,
+    };
     let config = Config {
-        monthly_reward: msg.monthly_reward.clone(),
-        total_value_locked: Coin {
-            denom: msg.monthly_reward.denom.clone(),
-            amount: Uint128::zero(),
-        },
-        eps: calculate_eps(&Config {
-            monthly_reward: msg.monthly_reward.clone(),
-            total_value_locked: Coin {
-                denom: msg.monthly_reward.denom.clone(),
-                amount: Uint128::new(1), // Avoid division by zero
-            },
-            eps: Uint128::zero(),
-            last_update_time: env.block.time.seconds(),
-            global_exchange_rate: Uint128::new(1_000_000),
-        }),
+        stake_asset: msg.stake_asset.validate(deps.api)?,
+        total_value_locked: Uint128::zero(),
+        total_effective_stake: Uint128::zero(),
         last_update_time: env.block.time.seconds(),
-        global_exchange_rate: Uint128::new(1_000_000),
+        reward_streams: vec![RewardStream::new(
+            msg.reward_asset.validate(deps.api)?,
+            msg.monthly_reward.unwrap_or_default(),
+        )],
+        undistributed_policy: msg
+            .undistributed_policy
+            .unwrap_or(UndistributedPolicy::Rollover),
+        unbonding_period: msg.unbonding_period.unwrap_or_default(),
+        delegate_stake: msg.delegate_stake.unwrap_or_default(),
+        lock_tiers: vec![],
+        passed_lock_expiries: vec![],
+        early_withdrawal_penalty: EarlyWithdrawalPenalty::default(),
+        pause_flags: PauseFlags::default(),
+        guardian: None,
     };
+    if config.delegate_stake
+        && (delegated_denom(&config).is_none() || config.unbonding_period == 0)
+    {
+        return Err(ContractError::InvalidDelegationConfig {});
+    }
+    // Staking rewards are paid out on every delegation change and need a
+    // stream to be shared through
+    if let Some(denom) = delegated_denom(&config) {
+        harvest_stream(&config, denom)?;
+    }
     save_config(deps.storage, &config)?;
-    Ok(Response::new().add_attribute("method", "instantiate"))
+    OWNERSHIP.save(
+        deps.storage,
+        &Ownership {
+            owner: Some(owner.clone()),
+            pending_owner: None,
+        },
+    )?;
+    Ok(Response::new()
+        .add_attribute("method", "instantiate")
+        .add_attribute("owner", owner))
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
@@ -46,9 +93,47 @@ pub fn execute(
     msg: ExecuteMsg,
 ) -> Result<Response, ContractError> {
     match msg {
-        ExecuteMsg::Stake { amount } => try_stake(deps, env, info, amount),
+        ExecuteMsg::Stake {} => try_stake(deps, env, info, None),
+        ExecuteMsg::StakeLocked { tier } => try_stake(deps, env, info, Some(tier)),
+        ExecuteMsg::Receive(msg) => try_receive(deps, env, info, msg),
         ExecuteMsg::Withdraw { amount } => try_withdraw(deps, env, info, amount),
+        ExecuteMsg::EmergencyWithdraw {} => try_emergency_withdraw(deps, env, info),
         ExecuteMsg::Claim {  } => try_claim(deps, env, info),
+        ExecuteMsg::Compound {} => try_compound(deps, env, info),
+        ExecuteMsg::SetAutoCompound { enabled } => {
+            try_set_auto_compound(deps, env, info, enabled)
+        }
+        ExecuteMsg::FundRewards { stream_id } => try_fund_rewards(deps, env, info, stream_id),
+        ExecuteMsg::ReclaimUndistributed {} => try_reclaim_undistributed(deps, env, info),
+        ExecuteMsg::UpdateOwnership(action) => try_update_ownership(deps, info, action),
+        ExecuteMsg::UpdateConfig(msg) => try_update_config(deps, env, info, msg),
+        ExecuteMsg::SetPauseFlags {
+            stake,
+            withdraw,
+            claim,
+            accrual,
+        } => try_set_pause_flags(deps, env, info, [stake, withdraw, claim, accrual]),
+        ExecuteMsg::SetGuardian { guardian } => try_set_guardian(deps, info, guardian),
+        ExecuteMsg::SetLockTiers { tiers } => try_set_lock_tiers(deps, info, tiers),
+        ExecuteMsg::AddRewardStream {
+            asset,
+            monthly_reward,
+        } => try_add_reward_stream(deps, env, info, asset, monthly_reward),
+        ExecuteMsg::UpdateRewardStream {
+            stream_id,
+            monthly_reward,
+        } => try_update_reward_stream(deps, env, info, stream_id, monthly_reward),
+        ExecuteMsg::ScheduleCampaign {
+            stream_id,
+            start,
+            end,
+            budget,
+        } => try_schedule_campaign(deps, env, info, stream_id, Campaign { start, end, budget }),
+        ExecuteMsg::UpdateValidators { add, remove } => {
+            try_update_validators(deps, env, info, add, remove)
+        }
+        ExecuteMsg::Harvest {} => try_harvest(deps, env),
+        ExecuteMsg::ReleaseUnbonded {} => try_release_unbonded(deps, env, info),
     }
 }
 
@@ -56,405 +141,3945 @@ fn try_stake(
     deps: DepsMut,
     env: Env,
     info: MessageInfo,
-    amount: Coin,
+    tier: Option<u64>,
+) -> Result<Response, ContractError> {
+    let config = read_config(deps.storage)?;
+    let amount = match &config.stake_asset {
+        AssetInfo::Native { denom } => must_pay(&info, denom)?,
+        AssetInfo::Cw20 { contract_addr } => {
+            return Err(ContractError::InvalidToken {
+                expected: contract_addr.to_string(),
+                got: "native funds".to_string(),
+            })
+        }
+    };
+    stake(deps, env, info.sender, amount, tier)
+}
+
+fn try_receive(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    msg: Cw20ReceiveMsg,
+) -> Result<Response, ContractError> {
+    if msg.amount.is_zero() {
+        return Err(ContractError::InvalidAmount {});
+    }
+
+    let token = AssetInfo::Cw20 {
+        contract_addr: info.sender,
+    };
+    let tier = match from_json(&msg.msg)? {
+        ReceiveMsg::Stake {} => None,
+        ReceiveMsg::StakeLocked { tier } => Some(tier),
+        ReceiveMsg::FundRewards { stream_id } => {
+            return fund_rewards(deps, env, stream_id, token, msg.amount)
+        }
+    };
+    let config = read_config(deps.storage)?;
+    if config.stake_asset != token {
+        return Err(ContractError::InvalidToken {
+            expected: config.stake_asset.to_string(),
+            got: token.to_string(),
+        });
+    }
+    let staker = deps.api.addr_validate(&msg.sender)?;
+    stake(deps, env, staker, msg.amount, tier)
+}
+
+/// Credits `amount` of the stake asset, already received by the contract,
+/// to `staker`, locked under lock tier `tier` if given.
+fn stake(
+    mut deps: DepsMut,
+    env: Env,
+    staker: Addr,
+    amount: Uint128,
+    tier: Option<u64>,
 ) -> Result<Response, ContractError> {
     let mut config = read_config(deps.storage)?;
-    let mut user = USERS.may_load(deps.storage, &info.sender)?.unwrap_or_else(|| User {
-        staked_amount: Coin {
-            denom: amount.denom.clone(),
-            amount: Uint128::zero(),
-        },
-        exchange_rate: config.global_exchange_rate,
+    assert_not_paused(config.pause_flags.stake, "stake")?;
+    let lock_tier = match tier {
+        Some(tier) => Some(
+            config
+                .lock_tiers
+                .get(tier as usize)
+                .cloned()
+                .ok_or(ContractError::UnknownLockTier { tier })?,
+        ),
+        None => None,
+    };
+    let mut user = USERS.may_load(deps.storage, &staker)?.unwrap_or_else(|| User {
+        staked_amount: Uint128::zero(),
+        effective_stake: Uint128::zero(),
+        locks: vec![],
         last_staked_time: env.block.time.seconds(),
-        rewards: Uint128::zero(),
+        deposits: vec![],
+        rewards: vec![],
+        auto_compound: false,
     });
 
-    update_global_state(&mut config, env.block.time.seconds())?;
-    let rewards = calculate_rewards(&config, &user, env.block.time.seconds());
-    user.rewards += rewards;
+    update_global_state(deps.storage, &mut config, env.block.time.seconds())?;
+    let withdrawn = withdraw_delegation_rewards(deps.as_ref(), &env, &mut config)?;
+    let compounded = settle(deps.storage, &mut config, &mut user)?;
 
-    config.total_value_locked.amount += amount.amount;
-    user.staked_amount.amount += amount.amount;
+    config.total_value_locked += amount;
+    user.staked_amount += amount;
+    let unlock_at = lock_tier
+        .as_ref()
+        .map(|lock_tier| unlock_time(env.block.time.seconds() + lock_tier.duration));
+    if let (Some(lock_tier), Some(unlock_at)) = (&lock_tier, unlock_at) {
+        let lock = Lock {
+            amount,
+            boost: lock_tier.boost,
+            unlock_at,
+        };
+        schedule_lock_expiry(deps.storage, &lock)?;
+        user.locks.push(lock);
+    }
+    update_effective_stake(&mut config, &mut user);
 
-    user.last_staked_time = env.block.time.seconds();
-    config.eps = calculate_eps(&config);
-    user.exchange_rate = config.global_exchange_rate;
+    let now = env.block.time.seconds();
+    let penalty = &config.early_withdrawal_penalty;
+    user.deposits.retain(|deposit| now - deposit.staked_at < penalty.period);
+    if !penalty.rate.is_zero() && penalty.period > 0 {
+        user.deposits.push(Deposit {
+            amount,
+            staked_at: now,
+            unlock_at: unlock_at.unwrap_or(now),
+        });
+    }
+    user.last_staked_time = now;
 
     save_config(deps.storage, &config)?;
-    USERS.save(deps.storage, &info.sender, &user)?;
+    USERS.save(deps.storage, &staker, &user)?;
 
-    Ok(Response::new()
+    let mut res = Response::new()
         .add_attribute("action", "stake")
-        .add_attribute("amount", amount.to_string()))
+        .add_attribute("staker", staker)
+        .add_attribute("amount", amount)
+        .add_attribute("asset", config.stake_asset.to_string());
+    if let Some(unlock_at) = unlock_at {
+        res = res.add_attribute("unlock_at", unlock_at.to_string());
+    }
+    res = res.add_messages(withdrawn);
+    if let Some(denom) = delegated_denom(&config) {
+        res = res.add_message(delegate(deps.branch().storage, denom, amount)?);
+    }
+    with_compounded(deps.storage, &config, res, compounded)
+}
+
+fn try_fund_rewards(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    stream_id: u64,
+) -> Result<Response, ContractError> {
+    let config = read_config(deps.storage)?;
+    let asset = config
+        .reward_streams
+        .get(stream_id as usize)
+        .ok_or(ContractError::UnknownRewardStream { stream_id })?
+        .asset
+        .clone();
+    let amount = match &asset {
+        AssetInfo::Native { denom } => must_pay(&info, denom)?,
+        AssetInfo::Cw20 { contract_addr } => {
+            return Err(ContractError::InvalidToken {
+                expected: contract_addr.to_string(),
+                got: "native funds".to_string(),
+            })
+        }
+    };
+    fund_rewards(deps, env, stream_id, asset, amount)
+}
+
+/// Adds `amount` of `asset`, already received by the contract, to the
+/// reserve of stream `stream_id`.
+fn fund_rewards(
+    deps: DepsMut,
+    env: Env,
+    stream_id: u64,
+    asset: AssetInfo,
+    amount: Uint128,
+) -> Result<Response, ContractError> {
+    // A stream paused on an empty reserve resumes from now, not retroactively
+    let mut config = read_config(deps.storage)?;
+    update_global_state(deps.storage, &mut config, env.block.time.seconds())?;
+
+    let stream = config
+        .reward_streams
+        .get_mut(stream_id as usize)
+        .ok_or(ContractError::UnknownRewardStream { stream_id })?;
+    if stream.asset != asset {
+        return Err(ContractError::InvalidToken {
+            expected: stream.asset.to_string(),
+            got: asset.to_string(),
+        });
+    }
+    stream.funded += amount;
+    save_config(deps.storage, &config)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "fund_rewards")
+        .add_attribute("stream_id", stream_id.to_string())
+        .add_attribute("amount", amount)
+        .add_attribute("asset", asset.to_string()))
+}
+
+/// Returns the amount of `denom` attached to the message, requiring it to be
+/// the only coin sent.
+fn must_pay(info: &MessageInfo, denom: &str) -> Result<Uint128, ContractError> {
+    let coin = match info.funds.as_slice() {
+        [] => return Err(ContractError::NoFunds {}),
+        [coin] => coin,
+        _ => return Err(ContractError::MultipleDenoms {}),
+    };
+    if coin.denom != denom {
+        return Err(ContractError::InvalidDenom {
+            expected: denom.to_string(),
+            got: coin.denom.clone(),
+        });
+    }
+    if coin.amount.is_zero() {
+        return Err(ContractError::InvalidAmount {});
+    }
+    Ok(coin.amount)
 }
 
 fn try_withdraw(
     deps: DepsMut,
     env: Env,
     info: MessageInfo,
-    amount: Coin,
+    amount: Uint128,
 ) -> Result<Response, ContractError> {
     let mut config = read_config(deps.storage)?;
+    assert_not_paused(config.pause_flags.withdraw, "withdraw")?;
     let mut user = USERS.load(deps.storage, &info.sender)?;
 
-    update_global_state(&mut config, env.block.time.seconds())?;
-    let rewards = calculate_rewards(&config, &user, env.block.time.seconds());
-    user.rewards += rewards;
+    if amount.is_zero() {
+        return Err(ContractError::InvalidAmount {});
+    }
+
+    update_global_state(deps.storage, &mut config, env.block.time.seconds())?;
+    let withdrawn = withdraw_delegation_rewards(deps.as_ref(), &env, &mut config)?;
+    let compounded = settle(deps.storage, &mut config, &mut user)?;
 
-    if user.staked_amount.amount < amount.amount {
+    if user.staked_amount < amount {
         return Err(ContractError::InsufficientStaked {});
     }
+    assert_unlocked(&user, amount, env.block.time.seconds())?;
 
-    config.total_value_locked.amount -= amount.amount;
-    user.staked_amount.amount -= amount.amount;
+    let penalty = withdraw_deposits(&config, &mut user, amount, env.block.time.seconds());
+    config.total_value_locked -= amount;
+    user.staked_amount -= amount;
+    update_effective_stake(&mut config, &mut user);
 
-    user.last_staked_time = env.block.time.seconds();
-    config.eps = calculate_eps(&config);
-    user.exchange_rate = config.global_exchange_rate;
+    let mut payouts = vec![(info.sender.clone(), amount - penalty)];
+    match config.early_withdrawal_penalty.treasury.clone() {
+        Some(treasury) => payouts.push((treasury, penalty)),
+        None if !penalty.is_zero() => redistribute_penalty(&mut config, &mut user, penalty)?,
+        None => {}
+    }
 
     save_config(deps.storage, &config)?;
     USERS.save(deps.storage, &info.sender, &user)?;
 
-    Ok(Response::new()
+    let mut res = Response::new()
         .add_attribute("action", "withdraw")
-        .add_attribute("amount", amount.to_string()))
-}
-#[cfg_attr(not(feature = "library"), entry_point)]
-pub fn query(deps: DepsMut, env: Env, msg: QueryMsg) -> StdResult<Binary> {
-    match msg {
-        QueryMsg::Configure {} => to_json_binary(&query_config(deps)?),
-        QueryMsg::User { address } => to_json_binary(&query_user(deps, env, address)?),
-    }
+        .add_attribute("amount", amount)
+        .add_attribute("penalty", penalty)
+        .add_attribute("asset", config.stake_asset.to_string())
+        .add_messages(withdrawn);
+    res = with_compounded(deps.storage, &config, res, compounded)?;
+    pay_withdrawal(deps.storage, &env, &config, res, amount, payouts)
 }
 
-fn try_claim(deps: DepsMut, env:Env, info: MessageInfo) -> Result<Response, ContractError> {
+/// Withdraws all of the sender's stake without touching the reward index, so
+/// it works when reward math fails and while the contract is paused. The full
+/// principal is returned: there is no early withdrawal penalty and locks are
+/// ignored, the rewards their boost earned being forfeited with the rest.
+/// Banked rewards are handed back to the streams as undistributed, rewards
+/// accrued since the last settlement are never computed and stay with the
+/// contract.
+fn try_emergency_withdraw(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+) -> Result<Response, ContractError> {
     let mut config = read_config(deps.storage)?;
-    let mut user = USERS.load(deps.storage, &info.sender)?;
-    update_global_state(&mut config, env.block.time.seconds())?;
-    let rewards = calculate_rewards(&config, &user, env.block.time.seconds());
-    if rewards == Uint128::zero() {
-        return Err(ContractError:: InvalidClaim {  });
+    let user = USERS.load(deps.storage, &info.sender)?;
+    let amount = user.staked_amount;
+    if amount.is_zero() {
+        return Err(ContractError::InsufficientStaked {});
+    }
 
+    // Boosts that already expired are out of the total, the others must not
+    // leave it again when they do
+    let mut expired = Uint128::zero();
+    for lock in &user.locks {
+        let boost = lock_boost(lock);
+        if lock.unlock_at <= config.last_update_time {
+            expired += boost;
+        } else if !boost.is_zero() {
+            let mut expiry = LOCK_EXPIRIES.load(deps.storage, lock.unlock_at)?;
+            expiry.boost -= boost;
+            LOCK_EXPIRIES.save(deps.storage, lock.unlock_at, &expiry)?;
+        }
     }
-    user.rewards = Uint128::zero();
-    user.exchange_rate = config.global_exchange_rate;
-    user.last_staked_time = env.block.time.seconds();
-    USERS.save(deps.storage, &info.sender, &user);
-    save_config(deps.storage, &config);
-    let reward_coin = Coin {
-        denom : config.monthly_reward.denom.clone(),
-        amount: rewards,
+    config.total_value_locked -= amount;
+    config.total_effective_stake -= user.effective_stake - expired;
+    for (stream, reward) in config.reward_streams.iter_mut().zip(&user.rewards) {
+        stream.undistributed_rewards += Decimal256::from_ratio(reward.rewards, 1u8);
+    }
+    // Only the remaining stakers share what the undelegation pays out
+    let withdrawn = withdraw_delegation_rewards(deps.as_ref(), &env, &mut config)?;
 
-    };
+    save_config(deps.storage, &config)?;
+    USERS.remove(deps.storage, &info.sender);
 
-    Ok(Response::new()
-    .add_attribute("action", "claim")
-    .add_attribute("amount", reward_coin.amount.to_string()))
+    let res = Response::new()
+        .add_attribute("action", "emergency_withdraw")
+        .add_attribute("amount", amount)
+        .add_attribute("asset", config.stake_asset.to_string())
+        .add_messages(withdrawn);
+    let payouts = vec![(info.sender.clone(), amount)];
+    pay_withdrawal(deps.storage, &env, &config, res, amount, payouts)
 }
 
+/// Fails with `StakeLocked` if `amount` exceeds the part of `user`'s stake
+/// not locked at `now`.
+fn assert_unlocked(user: &User, amount: Uint128, now: u64) -> Result<(), ContractError> {
+    let locks = user.locks.iter().filter(|lock| lock.unlock_at > now);
+    let locked: Uint128 = locks.clone().map(|lock| lock.amount).sum();
+    let available = user.staked_amount.saturating_sub(locked);
+    if amount > available {
+        let unlock_at = locks.map(|lock| lock.unlock_at).min().unwrap_or_default();
+        return Err(ContractError::StakeLocked {
+            available,
+            unlock_at,
+        });
+    }
+    Ok(())
+}
 
+/// Releases `amount` of withdrawn stake split between `payouts`, undelegating
+/// it first when delegating, either straight away or once unbonded.
+fn pay_withdrawal(
+    storage: &mut dyn Storage,
+    env: &Env,
+    config: &Config,
+    mut res: Response,
+    amount: Uint128,
+    payouts: Vec<(Addr, Uint128)>,
+) -> Result<Response, ContractError> {
+    if let Some(denom) = delegated_denom(config) {
+        res = res.add_messages(undelegate(storage, denom, amount)?);
+    }
+    let payouts = payouts.into_iter().filter(|(_, amount)| !amount.is_zero());
+    if config.unbonding_period == 0 {
+        for (recipient, amount) in payouts {
+            res = res.add_message(config.stake_asset.transfer_msg(&recipient, amount)?);
+        }
+        return Ok(res);
+    }
 
+    // A treasury's cut unbonds along with the payout, to be released by it
+    let release_at = env.block.time.seconds() + config.unbonding_period;
+    for (recipient, amount) in payouts {
+        let mut entries = UNBONDING.may_load(storage, &recipient)?.unwrap_or_default();
+        entries.push(UnbondingEntry { amount, release_at });
+        UNBONDING.save(storage, &recipient, &entries)?;
+    }
 
-
-fn query_config(deps: DepsMut) -> StdResult<ConfigResponse> {
-    let config = read_config(deps.storage)?;
-    Ok(ConfigResponse {
-        monthly_reward: config.monthly_reward,
-        total_value_locked: config.total_value_locked,
-        eps: config.eps,
-        global_exchange_rate: config.global_exchange_rate,
-    })
+    Ok(res.add_attribute("release_at", release_at.to_string()))
 }
-fn query_user(deps: DepsMut, env: Env, address: String) -> StdResult<UserResponse> {
-    let addr = deps.api.addr_validate(&address)?;
-    let user = USERS.load(deps.storage, &addr)?;
-    let config = read_config(deps.storage)?;
-    
-    let current_time = env.block.time.seconds();
-    let latest_rewards = calculate_rewards(&config, &user, current_time);
-    
-    Ok(UserResponse {
-        staked_amount: user.staked_amount,
-        exchange_rate: user.exchange_rate,
-        rewards: user.rewards + latest_rewards,
-    })
+
+/// Takes `amount` withdrawn at `now` out of `user`'s deposits, the newest
+/// unlocked ones first, and returns the part of it forfeited. Stake older
+/// than the deposits is never penalized.
+fn withdraw_deposits(config: &Config, user: &mut User, amount: Uint128, now: u64) -> Uint128 {
+    let penalty = &config.early_withdrawal_penalty;
+    let mut remaining = amount;
+    let mut forfeited = Uint128::zero();
+    for deposit in user.deposits.iter_mut().rev() {
+        if remaining.is_zero() {
+            break;
+        }
+        if deposit.unlock_at > now {
+            continue;
+        }
+        let taken = remaining.min(deposit.amount);
+        deposit.amount -= taken;
+        remaining -= taken;
+        let age = now.saturating_sub(deposit.staked_at);
+        if age < penalty.period {
+            forfeited += taken
+                .mul_floor(penalty.rate)
+                .multiply_ratio(penalty.period - age, penalty.period);
+        }
+    }
+    user.deposits.retain(|deposit| !deposit.amount.is_zero());
+    forfeited
 }
 
-fn update_global_state(config: &mut Config, current_time: u64) -> StdResult<()> {
-    let time_elapsed = current_time - config.last_update_time;
-    if time_elapsed > 0 && !config.total_value_locked.amount.is_zero() {
-        let rewards = config.eps * Uint128::from(time_elapsed as u128);
-        config.global_exchange_rate += rewards.multiply_ratio(Uint128::new(1_000_000), config.total_value_locked.amount);
-        println!("Updating Global State - Time Elapsed: {}, Rewards: {}, New Global Exchange Rate: {}", time_elapsed, rewards, config.global_exchange_rate);
+/// Shares `penalty` between the stakers other than the settled `user` who
+/// paid it, through the first reward stream paid in the stake asset.
+fn redistribute_penalty(
+    config: &mut Config,
+    user: &mut User,
+    penalty: Uint128,
+) -> Result<(), ContractError> {
+    let stream_id = config
+        .reward_streams
+        .iter()
+        .position(|stream| stream.asset == config.stake_asset)
+        .ok_or(ContractError::NoPenaltyStream {})?;
+    let others = config.total_effective_stake - user.effective_stake;
+    let stream = &mut config.reward_streams[stream_id];
+    let amount = Decimal256::from_ratio(penalty, 1u8);
+    // Like harvested rewards, penalties are paid for up front
+    stream.funded += penalty;
+    stream.distributed = stream
+        .distributed
+        .checked_add(amount)
+        .map_err(StdError::from)?;
+    if others.is_zero() {
+        stream.undistributed_rewards = stream
+            .undistributed_rewards
+            .checked_add(amount)
+            .map_err(StdError::from)?;
+    } else {
+        distribute(stream, others, amount)?;
     }
-    config.last_update_time = current_time;
+    // Skipping the user's snapshot past the share keeps them from earning it
+    user.rewards[stream_id].exchange_rate = stream.global_exchange_rate;
     Ok(())
 }
 
+fn try_release_unbonded(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+) -> Result<Response, ContractError> {
+    // Matured entries left the pool already, pausing withdrawals keeps them
+    // releasable so an emergency withdrawal is never stuck
+    let config = read_config(deps.storage)?;
+    let entries = UNBONDING
+        .may_load(deps.storage, &info.sender)?
+        .unwrap_or_default();
+    let (matured, pending): (Vec<_>, Vec<_>) = entries
+        .into_iter()
+        .partition(|entry| entry.release_at <= env.block.time.seconds());
+    if matured.is_empty() {
+        return Err(ContractError::NothingToRelease {});
+    }
+
+    if pending.is_empty() {
+        UNBONDING.remove(deps.storage, &info.sender);
+    } else {
+        UNBONDING.save(deps.storage, &info.sender, &pending)?;
+    }
 
+    let amount: Uint128 = matured.iter().map(|entry| entry.amount).sum();
 
+    Ok(Response::new()
+        .add_message(config.stake_asset.transfer_msg(&info.sender, amount)?)
+        .add_attribute("action", "release_unbonded")
+        .add_attribute("amount", amount)
+        .add_attribute("asset", config.stake_asset.to_string()))
+}
 
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn migrate(mut deps: DepsMut, env: Env, msg: MigrateMsg) -> Result<Response, ContractError> {
+    let previous = match CONTRACT.may_load(deps.storage)? {
+        Some(stored) => {
+            if stored.contract != CONTRACT_NAME {
+                return Err(ContractError::InvalidMigration {
+                    previous_contract: stored.contract,
+                });
+            }
+            let version: Version = stored.version.parse()?;
+            if version > CONTRACT_VERSION.parse()? {
+                return Err(ContractError::CannotDowngrade {
+                    previous_version: stored.version,
+                });
+            }
+            Some(version)
+        }
+        None => None,
+    };
 
+    let owner = msg
+        .owner
+        .map(|owner| deps.api.addr_validate(&owner))
+        .transpose()?;
+    migrate_state(deps.branch(), &env, previous.as_ref(), owner)?;
+    set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
 
-fn calculate_eps(config: &Config) -> Uint128 {
-    if config.total_value_locked.amount.is_zero() {
-        Uint128::zero()
-    } else {
-        // Monthly reward divided by seconds in a month (30 days) and then divided by TVL
-        let monthly_seconds = 30 * 24 * 60 * 60; 
-        let reward_per_second = config.monthly_reward.amount.u128() as f64 / monthly_seconds as f64;
-        let eps = reward_per_second / config.total_value_locked.amount.u128() as f64;
-        let eps_uint128 = Uint128::from((eps * 1_000_000_000.0).round() as u128);
-        println!(
-            "Calculating EPS - Monthly Reward: {}, TVL: {}, EPS: {}",
-            config.monthly_reward.amount, config.total_value_locked.amount, eps_uint128
-        );
-        eps_uint128
-    }
+    Ok(Response::new()
+        .add_attribute("action", "migrate")
+        .add_attribute(
+            "previous_version",
+            previous.map_or_else(|| "none".to_string(), |version| version.to_string()),
+        )
+        .add_attribute("version", CONTRACT_VERSION))
 }
 
- 
-
-fn calculate_rewards(config: &Config, user: &User, current_time: u64) -> Uint128 {
-    if config.total_value_locked.amount.is_zero() {
-        return Uint128::zero();
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
+    match msg {
+        QueryMsg::Configure {} => to_json_binary(&query_config(deps)?),
+        QueryMsg::User { address } => to_json_binary(&query_user(deps, env, address)?),
+        QueryMsg::Undistributed {} => to_json_binary(&query_undistributed(deps, env)?),
+        QueryMsg::Ownership {} => to_json_binary(&OWNERSHIP.load(deps.storage)?),
+        QueryMsg::Unbonding { address } => to_json_binary(&query_unbonding(deps, address)?),
+        QueryMsg::Rewards { address } => to_json_binary(&query_rewards(deps, env, address)?),
+        QueryMsg::RewardReserve {} => to_json_binary(&query_reward_reserve(deps, env)?),
+        QueryMsg::Campaigns { start_after, limit } => {
+            to_json_binary(&query_campaigns(deps, env, start_after, limit)?)
+        }
+        QueryMsg::Validators {} => to_json_binary(&query_validators(deps)?),
+        QueryMsg::PauseFlags {} => to_json_binary(&query_pause_flags(deps)?),
+        QueryMsg::SimulateRewards {
+            address,
+            at_time,
+            extra_stake,
+        } => to_json_binary(&query_simulate_rewards(deps, env, address, at_time, extra_stake)?),
+        QueryMsg::Apr {} => to_json_binary(&query_apr(deps, env)?),
+        QueryMsg::AllStakers { start_after, limit } => {
+            to_json_binary(&query_all_stakers(deps, env, start_after, limit)?)
+        }
+        QueryMsg::SimulateWithdraw { address, amount } => {
+            to_json_binary(&query_simulate_withdraw(deps, env, address, amount)?)
+        }
     }
-    let exchange_rate_diff = config.global_exchange_rate - user.exchange_rate;
-    let rewards = user.staked_amount.amount.multiply_ratio(exchange_rate_diff, Uint128::new(1_000_000));
-    println!(
-        "Calculating Rewards - Exchange Rate Diff: {}, User Staked Amount: {}, Rewards: {}",
-        exchange_rate_diff, user.staked_amount.amount, rewards
-    );
-    rewards
 }
 
+fn try_claim(deps: DepsMut, env: Env, info: MessageInfo) -> Result<Response, ContractError> {
+    let mut config = read_config(deps.storage)?;
+    assert_not_paused(config.pause_flags.claim, "claim")?;
+    let mut user = USERS.load(deps.storage, &info.sender)?;
 
+    update_global_state(deps.storage, &mut config, env.block.time.seconds())?;
+    let withdrawn = withdraw_delegation_rewards(deps.as_ref(), &env, &mut config)?;
+    let compounded = settle(deps.storage, &mut config, &mut user)?;
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
-    use cosmwasm_std::{coins, from_binary};
-
-    #[test]
-    fn initialization() {
-        let mut deps = mock_dependencies();
-        let env = mock_env();
-        let info = mock_info("creator", &[]);
-        let msg = InstantiateMsg {
-            monthly_reward: Coin {
-                denom: "orai".to_string(),
-                amount: Uint128::new(1000000),
-            },
-            eps: Uint128::new(1),
-        };
-    
-        let res = instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
-        assert_eq!(0, res.messages.len());
-    
-        let res = query(deps.as_mut(), env, QueryMsg::Configure {}).unwrap();
-        let config: ConfigResponse = from_binary(&res).unwrap();
-        assert_eq!(config.monthly_reward.amount, Uint128::new(1000000));
-        assert_eq!(config.total_value_locked.amount, Uint128::zero());
+    let mut res = Response::new().add_attribute("action", "claim");
+    for (stream, reward) in config.reward_streams.iter_mut().zip(user.rewards.iter_mut()) {
+        let amount = std::mem::take(&mut reward.rewards);
+        if amount.is_zero() {
+            continue;
+        }
+        stream.claimed += amount;
+        res = res
+            .add_message(stream.asset.transfer_msg(&info.sender, amount)?)
+            .add_attribute("amount", amount)
+            .add_attribute("asset", stream.asset.to_string());
+    }
+    if res.messages.is_empty() {
+        return Err(ContractError::InvalidClaim {});
     }
-    
 
-    #[test]
-    fn stake_tokens() {
-        let mut deps = mock_dependencies();
-        let env = mock_env();
-        let info = mock_info("creator", &[]);
-        let msg = InstantiateMsg {
-            monthly_reward: Coin {
-                denom: "orai".to_string(),
-                amount: Uint128::new(1000000),
-            },
-            eps: Uint128::new(1),
-        };
-        instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
-    
-        let info = mock_info("alice", &coins(100, "orai"));
-        let msg = ExecuteMsg::Stake {
-            amount: Coin {
-                denom: "orai".to_string(),
-                amount: Uint128::new(100),
-            },
-        };
-        let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
-        assert_eq!(res.attributes[0].value, "stake");
-    
-        let res = query(deps.as_mut(), env.clone(), QueryMsg::User { address: "alice".to_string() }).unwrap();
-        let user: UserResponse = from_binary(&res).unwrap();
-        assert_eq!(user.staked_amount.amount, Uint128::new(100));
+    USERS.save(deps.storage, &info.sender, &user)?;
+    save_config(deps.storage, &config)?;
+
+    let res = res.add_messages(withdrawn);
+    with_compounded(deps.storage, &config, res, compounded)
+}
+
+fn try_compound(deps: DepsMut, env: Env, info: MessageInfo) -> Result<Response, ContractError> {
+    let mut config = read_config(deps.storage)?;
+    assert_not_paused(config.pause_flags.claim, "claim")?;
+    let mut user = USERS.load(deps.storage, &info.sender)?;
+    if !pays_stake_asset(&config) {
+        return Err(ContractError::NoCompoundStream {});
     }
-    
 
-    #[test]
-    fn withdraw_tokens() {
-        let mut deps = mock_dependencies();
-        let mut env = mock_env();
-        let info = mock_info("creator", &[]);
-        let msg = InstantiateMsg {
-            monthly_reward: Coin {
-                denom: "orai".to_string(),
-                amount: Uint128::new(1000000),
-            },
-            eps: Uint128::new(1),
-        };
-        instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
-    
-        let info = mock_info("alice", &coins(100, "orai"));
-        let msg = ExecuteMsg::Stake {
-            amount: Coin {
-                denom: "orai".to_string(),
-                amount: Uint128::new(100),
-            },
-        };
-        execute(deps.as_mut(), env.clone(), info, msg).unwrap();
-    
-        env.block.time = env.block.time.plus_seconds(86400); // 1 day later
-    
-        let info = mock_info("alice", &[]);
-        let msg = ExecuteMsg::Withdraw {
-            amount: Coin {
-                denom: "orai".to_string(),
-                amount: Uint128::new(50),
-            },
-        };
-        let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
-        assert_eq!(res.attributes[0].value, "withdraw");
-    
-        let res = query(deps.as_mut(), env, QueryMsg::User { address: "alice".to_string() }).unwrap();
-        let user: UserResponse = from_binary(&res).unwrap();
-        assert_eq!(user.staked_amount.amount, Uint128::new(50));
+    update_global_state(deps.storage, &mut config, env.block.time.seconds())?;
+    let withdrawn = withdraw_delegation_rewards(deps.as_ref(), &env, &mut config)?;
+    let compounded =
+        settle(deps.storage, &mut config, &mut user)? + compound(&mut config, &mut user);
+    if compounded.is_zero() {
+        return Err(ContractError::InvalidClaim {});
     }
-    
+    update_effective_stake(&mut config, &mut user);
 
-    #[test]
-fn two_person_acts() {
-    let mut deps = mock_dependencies();
-    let mut env = mock_env();
-    let info = mock_info("creator", &[]);
-    let msg = InstantiateMsg {
-        monthly_reward: Coin {
-            denom: "orai".to_string(),
-            amount: Uint128::new(1000000),
-        },
-        eps: Uint128::new(1),
-    };
-    instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+    USERS.save(deps.storage, &info.sender, &user)?;
+    save_config(deps.storage, &config)?;
 
-    // Alice stakes 100 ORAI
-    let info = mock_info("alice", &coins(100, "orai"));
-    let msg = ExecuteMsg::Stake {
-        amount: Coin {
-            denom: "orai".to_string(),
-            amount: Uint128::new(100),
-        },
-    };
-    execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+    let res = Response::new()
+        .add_attribute("action", "compound")
+        .add_messages(withdrawn);
+    with_compounded(deps.storage, &config, res, compounded)
+}
 
-    // Bob stakes 200 ORAI
-    let info = mock_info("bob", &coins(200, "orai"));
-    let msg = ExecuteMsg::Stake {
-        amount: Coin {
-            denom: "orai".to_string(),
-            amount: Uint128::new(200),
-        },
-    };
-    execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+fn try_set_auto_compound(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    enabled: bool,
+) -> Result<Response, ContractError> {
+    let mut config = read_config(deps.storage)?;
+    let mut user = USERS.load(deps.storage, &info.sender)?;
+    if enabled {
+        assert_not_paused(config.pause_flags.claim, "claim")?;
+        if !pays_stake_asset(&config) {
+            return Err(ContractError::NoCompoundStream {});
+        }
+    }
 
-    // Forward time
-    env.block.time = env.block.time.plus_seconds(1_296_000);
-    update_global_state(&mut read_config(deps.as_mut().storage).unwrap(), env.block.time.seconds()).unwrap();
+    update_global_state(deps.storage, &mut config, env.block.time.seconds())?;
+    let withdrawn = withdraw_delegation_rewards(deps.as_ref(), &env, &mut config)?;
+    user.auto_compound = enabled;
+    let compounded = settle(deps.storage, &mut config, &mut user)?;
 
-    // Bob withdraws half (100 ORAI)
-    let info = mock_info("bob", &[]);
-    let msg = ExecuteMsg::Withdraw {
-        amount: Coin {
-            denom: "orai".to_string(),
-            amount: Uint128::new(100),
-        },
-    };
-    execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+    USERS.save(deps.storage, &info.sender, &user)?;
+    save_config(deps.storage, &config)?;
 
-    // Forward time
-    env.block.time = env.block.time.plus_seconds(2_206_000);
-    update_global_state(&mut read_config(deps.as_mut().storage).unwrap(), env.block.time.seconds()).unwrap();
+    let res = Response::new()
+        .add_attribute("action", "set_auto_compound")
+        .add_attribute("enabled", enabled.to_string())
+        .add_messages(withdrawn);
+    with_compounded(deps.storage, &config, res, compounded)
+}
 
-    // Alice withdraws all (100 ORAI)
-    let info = mock_info("alice", &[]);
-    let msg = ExecuteMsg::Withdraw {
-        amount: Coin {
-            denom: "orai".to_string(),
-            amount: Uint128::new(100),
-        },
-    };
-    execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+/// Whether any reward stream pays in the stake asset, so it can be
+/// compounded.
+fn pays_stake_asset(config: &Config) -> bool {
+    config
+        .reward_streams
+        .iter()
+        .any(|stream| stream.asset == config.stake_asset)
+}
 
-    // Bob withdraws the rest (100 ORAI)
-    let info = mock_info("bob", &[]);
-    let msg = ExecuteMsg::Withdraw {
-        amount: Coin {
-            denom: "orai".to_string(),
-            amount: Uint128::new(100),
-        },
-    };
-    execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+/// Reports rewards compounded while settling and delegates them along with
+/// the rest of the stake.
+fn with_compounded(
+    storage: &mut dyn Storage,
+    config: &Config,
+    res: Response,
+    compounded: Uint128,
+) -> Result<Response, ContractError> {
+    if compounded.is_zero() {
+        return Ok(res);
+    }
+    let mut res = res.add_attribute("compounded", compounded);
+    if let Some(denom) = delegated_denom(config) {
+        res = res.add_message(delegate(storage, denom, compounded)?);
+    }
+    Ok(res)
+}
 
-    // Query Alice
-    let res = query(deps.as_mut(), env.clone(), QueryMsg::User { address: "alice".to_string() }).unwrap();
-    let user: UserResponse = from_binary(&res).unwrap();
-    println!("Alice - Staked amount: {}, Rewards: {}", user.staked_amount.amount, user.rewards);
-    assert_eq!(user.staked_amount.amount, Uint128::zero());
-    assert!(user.rewards > Uint128::zero());
+fn try_reclaim_undistributed(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+) -> Result<Response, ContractError> {
+    assert_owner(deps.as_ref(), &info.sender)?;
 
-    // Query Bob
-    let res = query(deps.as_mut(), env, QueryMsg::User { address: "bob".to_string() }).unwrap();
-    let user: UserResponse = from_binary(&res).unwrap();
-    println!("Bob - Staked amount: {}, Rewards: {}", user.staked_amount.amount, user.rewards);
-    assert_eq!(user.staked_amount.amount, Uint128::zero());
-    assert!(user.rewards > Uint128::zero());
+    let mut config = read_config(deps.storage)?;
+    update_global_state(deps.storage, &mut config, env.block.time.seconds())?;
+
+    let mut res = Response::new().add_attribute("action", "reclaim_undistributed");
+    for stream in config.reward_streams.iter_mut() {
+        let amount = stream.undistributed_rewards.to_uint_floor();
+        if amount.is_zero() {
+            continue;
+        }
+        // Any fractional remainder stays behind for the next reclaim
+        stream.undistributed_rewards = stream
+            .undistributed_rewards
+            .checked_sub(Decimal256::from_ratio(amount, 1u8))
+            .map_err(StdError::from)?;
+
+        let amount = Uint128::try_from(amount).map_err(StdError::from)?;
+        stream.claimed += amount;
+        res = res
+            .add_message(stream.asset.transfer_msg(&info.sender, amount)?)
+            .add_attribute("amount", amount)
+            .add_attribute("asset", stream.asset.to_string());
+    }
+    if res.messages.is_empty() {
+        return Err(ContractError::InvalidClaim {});
+    }
+    save_config(deps.storage, &config)?;
+
+    Ok(res)
 }
 
-#[test]
-fn claim_rewards() {
-    let mut deps = mock_dependencies();
-    let mut env = mock_env();
-    let info = mock_info("creator", &[]);
-    let msg = InstantiateMsg {
-        monthly_reward: Coin {
-            denom: "orai".to_string(),
-            amount: Uint128::new(1000000),
-        },
-        eps: Uint128::new(1),
-    };
-    instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+fn try_update_ownership(
+    deps: DepsMut,
+    info: MessageInfo,
+    action: OwnershipAction,
+) -> Result<Response, ContractError> {
+    let mut ownership = OWNERSHIP.load(deps.storage)?;
+    match action {
+        OwnershipAction::TransferOwnership { new_owner } => {
+            assert_owner(deps.as_ref(), &info.sender)?;
+            ownership.pending_owner = Some(deps.api.addr_validate(&new_owner)?);
+        }
+        OwnershipAction::AcceptOwnership => {
+            if ownership.pending_owner.as_ref() != Some(&info.sender) {
+                return Err(ContractError::Unauthorized {});
+            }
+            ownership.owner = ownership.pending_owner.take();
+        }
+        OwnershipAction::RenounceOwnership => {
+            assert_owner(deps.as_ref(), &info.sender)?;
+            ownership.owner = None;
+            ownership.pending_owner = None;
+        }
+    }
+    OWNERSHIP.save(deps.storage, &ownership)?;
 
-    // Alice stakes 100 ORAI
-    let info = mock_info("alice", &coins(100, "orai"));
-    let msg = ExecuteMsg::Stake {
-        amount: Coin {
-            denom: "orai".to_string(),
-            amount: Uint128::new(100),
-        },
+    Ok(Response::new()
+        .add_attribute("action", "update_ownership")
+        .add_attribute("owner", format_addr(&ownership.owner))
+        .add_attribute("pending_owner", format_addr(&ownership.pending_owner)))
+}
+
+fn try_update_config(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    msg: UpdateConfigMsg,
+) -> Result<Response, ContractError> {
+    assert_owner(deps.as_ref(), &info.sender)?;
+
+    // Everything up to now is owed at the old settings
+    let mut config = read_config(deps.storage)?;
+    update_global_state(deps.storage, &mut config, env.block.time.seconds())?;
+
+    let mut res = Response::new().add_attribute("action", "update_config");
+    if let Some(monthly_reward) = msg.monthly_reward {
+        let stream = &mut config.reward_streams[0];
+        res = res
+            .add_attribute("old_monthly_reward", stream.monthly_reward)
+            .add_attribute("new_monthly_reward", monthly_reward);
+        stream.monthly_reward = monthly_reward;
+        stream.eps = calculate_eps(monthly_reward);
+    }
+    if let Some(undistributed_policy) = msg.undistributed_policy {
+        res = res
+            .add_attribute(
+                "old_undistributed_policy",
+                format!("{:?}", config.undistributed_policy),
+            )
+            .add_attribute("new_undistributed_policy", format!("{:?}", undistributed_policy));
+        config.undistributed_policy = undistributed_policy;
+    }
+    if let Some(unbonding_period) = msg.unbonding_period {
+        if config.delegate_stake && unbonding_period == 0 {
+            return Err(ContractError::InvalidDelegationConfig {});
+        }
+        res = res
+            .add_attribute("old_unbonding_period", config.unbonding_period.to_string())
+            .add_attribute("new_unbonding_period", unbonding_period.to_string());
+        config.unbonding_period = unbonding_period;
+    }
+    if let Some(penalty) = msg.early_withdrawal_penalty {
+        if penalty.rate > Decimal::one() || (!penalty.rate.is_zero() && penalty.period == 0) {
+            return Err(ContractError::InvalidPenalty {});
+        }
+        let treasury = match penalty.treasury {
+            Some(treasury) => Some(deps.api.addr_validate(treasury.as_str())?),
+            None if !penalty.rate.is_zero() && !pays_stake_asset(&config) => {
+                return Err(ContractError::NoPenaltyStream {})
+            }
+            None => None,
+        };
+        res = res
+            .add_attribute("penalty_rate", penalty.rate.to_string())
+            .add_attribute("penalty_period", penalty.period.to_string())
+            .add_attribute("penalty_treasury", format_addr(&treasury));
+        config.early_withdrawal_penalty = EarlyWithdrawalPenalty {
+            treasury,
+            ..penalty
+        };
+    }
+    save_config(deps.storage, &config)?;
+
+    Ok(res)
+}
+
+fn try_set_pause_flags(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    flags: [Option<bool>; 4],
+) -> Result<Response, ContractError> {
+    let mut config = read_config(deps.storage)?;
+    let ownership = OWNERSHIP.load(deps.storage)?;
+    if ownership.owner.as_ref() != Some(&info.sender) {
+        let resumes = flags.contains(&Some(false));
+        if config.guardian.as_ref() != Some(&info.sender) || resumes {
+            return Err(ContractError::Unauthorized {});
+        }
+    }
+
+    // Accrual up to now is owed whether it is being paused or resumed
+    update_global_state(deps.storage, &mut config, env.block.time.seconds())?;
+    let [stake, withdraw, claim, accrual] = flags;
+    let pause_flags = &mut config.pause_flags;
+    pause_flags.stake = stake.unwrap_or(pause_flags.stake);
+    pause_flags.withdraw = withdraw.unwrap_or(pause_flags.withdraw);
+    pause_flags.claim = claim.unwrap_or(pause_flags.claim);
+    pause_flags.accrual = accrual.unwrap_or(pause_flags.accrual);
+    save_config(deps.storage, &config)?;
+
+    let pause_flags = &config.pause_flags;
+    Ok(Response::new()
+        .add_attribute("action", "set_pause_flags")
+        .add_attribute("stake", pause_flags.stake.to_string())
+        .add_attribute("withdraw", pause_flags.withdraw.to_string())
+        .add_attribute("claim", pause_flags.claim.to_string())
+        .add_attribute("accrual", pause_flags.accrual.to_string()))
+}
+
+fn try_set_guardian(
+    deps: DepsMut,
+    info: MessageInfo,
+    guardian: Option<String>,
+) -> Result<Response, ContractError> {
+    assert_owner(deps.as_ref(), &info.sender)?;
+
+    let mut config = read_config(deps.storage)?;
+    config.guardian = guardian
+        .map(|guardian| deps.api.addr_validate(&guardian))
+        .transpose()?;
+    save_config(deps.storage, &config)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "set_guardian")
+        .add_attribute("guardian", format_addr(&config.guardian)))
+}
+
+fn try_set_lock_tiers(
+    deps: DepsMut,
+    info: MessageInfo,
+    tiers: Vec<LockTier>,
+) -> Result<Response, ContractError> {
+    assert_owner(deps.as_ref(), &info.sender)?;
+    if tiers
+        .iter()
+        .any(|tier| tier.duration == 0 || tier.boost < Decimal::one())
+    {
+        return Err(ContractError::InvalidLockTier {});
+    }
+
+    // Existing locks carry their own boost, so nothing needs settling
+    let mut config = read_config(deps.storage)?;
+    let durations: Vec<String> = tiers.iter().map(|tier| tier.duration.to_string()).collect();
+    let boosts: Vec<String> = tiers.iter().map(|tier| tier.boost.to_string()).collect();
+    config.lock_tiers = tiers;
+    save_config(deps.storage, &config)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "set_lock_tiers")
+        .add_attribute("durations", format_list(&durations))
+        .add_attribute("boosts", format_list(&boosts)))
+}
+
+fn try_add_reward_stream(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    asset: AssetInfo,
+    monthly_reward: Uint128,
+) -> Result<Response, ContractError> {
+    assert_owner(deps.as_ref(), &info.sender)?;
+
+    // The new stream's index starts at zero from now on
+    let mut config = read_config(deps.storage)?;
+    update_global_state(deps.storage, &mut config, env.block.time.seconds())?;
+
+    let asset = asset.validate(deps.api)?;
+    let stream_id = config.reward_streams.len();
+    config
+        .reward_streams
+        .push(RewardStream::new(asset.clone(), monthly_reward));
+    save_config(deps.storage, &config)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "add_reward_stream")
+        .add_attribute("stream_id", stream_id.to_string())
+        .add_attribute("asset", asset.to_string())
+        .add_attribute("monthly_reward", monthly_reward))
+}
+
+fn try_update_reward_stream(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    stream_id: u64,
+    monthly_reward: Uint128,
+) -> Result<Response, ContractError> {
+    assert_owner(deps.as_ref(), &info.sender)?;
+
+    // Everything up to now is owed at the old rate
+    let mut config = read_config(deps.storage)?;
+    update_global_state(deps.storage, &mut config, env.block.time.seconds())?;
+
+    let stream = config
+        .reward_streams
+        .get_mut(stream_id as usize)
+        .ok_or(ContractError::UnknownRewardStream { stream_id })?;
+    let old_monthly_reward = stream.monthly_reward;
+    stream.monthly_reward = monthly_reward;
+    stream.eps = calculate_eps(monthly_reward);
+    save_config(deps.storage, &config)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "update_reward_stream")
+        .add_attribute("stream_id", stream_id.to_string())
+        .add_attribute("old_monthly_reward", old_monthly_reward)
+        .add_attribute("new_monthly_reward", monthly_reward))
+}
+
+fn try_schedule_campaign(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    stream_id: u64,
+    campaign: Campaign,
+) -> Result<Response, ContractError> {
+    assert_owner(deps.as_ref(), &info.sender)?;
+    if campaign.start < env.block.time.seconds() || campaign.end <= campaign.start {
+        return Err(ContractError::InvalidCampaign {});
+    }
+
+    let mut config = read_config(deps.storage)?;
+    update_global_state(deps.storage, &mut config, env.block.time.seconds())?;
+
+    let stream = config
+        .reward_streams
+        .get_mut(stream_id as usize)
+        .ok_or(ContractError::UnknownRewardStream { stream_id })?;
+    let res = Response::new()
+        .add_attribute("action", "schedule_campaign")
+        .add_attribute("stream_id", stream_id.to_string())
+        .add_attribute("start", campaign.start.to_string())
+        .add_attribute("end", campaign.end.to_string())
+        .add_attribute("budget", campaign.budget);
+    stream.campaigns.push(campaign);
+    save_config(deps.storage, &config)?;
+
+    Ok(res)
+}
+
+fn try_update_validators(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    add: Vec<String>,
+    remove: Vec<String>,
+) -> Result<Response, ContractError> {
+    assert_owner(deps.as_ref(), &info.sender)?;
+    let mut config = read_config(deps.storage)?;
+    let denom = delegated_denom(&config)
+        .ok_or(ContractError::InvalidDelegationConfig {})?
+        .to_string();
+    harvest_stream(&config, &denom)?;
+
+    // Redelegating pays out the pending rewards of the source validator
+    update_global_state(deps.storage, &mut config, env.block.time.seconds())?;
+    let withdrawn = withdraw_delegation_rewards(deps.as_ref(), &env, &mut config)?;
+    save_config(deps.storage, &config)?;
+
+    for validator in &add {
+        if deps.querier.query_validator(validator)?.is_none() {
+            return Err(ContractError::UnknownValidator {
+                validator: validator.clone(),
+            });
+        }
+        if !VALIDATORS.has(deps.storage, validator) {
+            VALIDATORS.save(deps.storage, validator, &Uint128::zero())?;
+        }
+    }
+
+    let mut res = Response::new().add_messages(withdrawn);
+    for validator in &remove {
+        let delegated = VALIDATORS
+            .may_load(deps.storage, validator)?
+            .ok_or_else(|| ContractError::UnknownValidator {
+                validator: validator.clone(),
+            })?;
+        VALIDATORS.remove(deps.storage, validator);
+        if delegated.is_zero() {
+            continue;
+        }
+        let (dst_validator, dst_delegated) = least_delegated(deps.storage)?;
+        VALIDATORS.save(deps.storage, &dst_validator, &(dst_delegated + delegated))?;
+        res = res.add_message(StakingMsg::Redelegate {
+            src_validator: validator.clone(),
+            dst_validator,
+            amount: coin(delegated.u128(), &denom),
+        });
+    }
+
+    Ok(res
+        .add_attribute("action", "update_validators")
+        .add_attribute("added", format_list(&add))
+        .add_attribute("removed", format_list(&remove)))
+}
+
+fn try_harvest(deps: DepsMut, env: Env) -> Result<Response, ContractError> {
+    let config = read_config(deps.storage)?;
+    let denom = delegated_denom(&config).ok_or(ContractError::InvalidDelegationConfig {})?;
+    harvest_stream(&config, denom)?;
+
+    let mut msgs: Vec<SubMsg> = VALIDATORS
+        .range(deps.storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?
+        .into_iter()
+        .filter(|(_, delegated)| !delegated.is_zero())
+        .map(|(validator, _)| SubMsg::new(DistributionMsg::WithdrawDelegatorReward { validator }))
+        .collect();
+    // Replying on the last withdrawal sees the rewards of all of them
+    let last = msgs.last_mut().ok_or(ContractError::NoValidators {})?;
+    *last = SubMsg::reply_on_success(last.msg.clone(), HARVEST_REPLY_ID);
+
+    let balance = deps
+        .querier
+        .query_balance(&env.contract.address, denom)?
+        .amount;
+    HARVEST_BALANCE.save(deps.storage, &balance)?;
+
+    Ok(Response::new()
+        .add_submessages(msgs)
+        .add_attribute("action", "harvest"))
+}
+
+/// Id of the reply to the last reward withdrawal of a harvest.
+const HARVEST_REPLY_ID: u64 = 1;
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn reply(deps: DepsMut, env: Env, msg: Reply) -> Result<Response, ContractError> {
+    match msg.id {
+        HARVEST_REPLY_ID => harvest_reply(deps, env),
+        id => Err(StdError::generic_err(format!("Unknown reply id {}", id)).into()),
+    }
+}
+
+/// Shares the rewards withdrawn since `try_harvest` recorded the balance
+/// between stakers, on top of the stream's own emission.
+fn harvest_reply(deps: DepsMut, env: Env) -> Result<Response, ContractError> {
+    let mut config = read_config(deps.storage)?;
+    let denom = delegated_denom(&config)
+        .ok_or(ContractError::InvalidDelegationConfig {})?
+        .to_string();
+    let before = HARVEST_BALANCE.load(deps.storage)?;
+    HARVEST_BALANCE.remove(deps.storage);
+    let balance = deps
+        .querier
+        .query_balance(&env.contract.address, &denom)?
+        .amount;
+    let harvested = balance.saturating_sub(before);
+
+    update_global_state(deps.storage, &mut config, env.block.time.seconds())?;
+    let stream_id = harvest_stream(&config, &denom)?;
+    share_harvested(&mut config, stream_id, harvested)?;
+    save_config(deps.storage, &config)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "harvest_reply")
+        .add_attribute("stream_id", stream_id.to_string())
+        .add_attribute("amount", harvested))
+}
+
+/// Shares `harvested` staking rewards between stakers through stream
+/// `stream_id`, on top of its own emission.
+fn share_harvested(
+    config: &mut Config,
+    stream_id: usize,
+    harvested: Uint128,
+) -> Result<(), ContractError> {
+    let total_staked = config.total_effective_stake;
+    let stream = &mut config.reward_streams[stream_id];
+    let amount = Decimal256::from_ratio(harvested, 1u8);
+    // Harvested rewards are paid for up front, they never touch the reserve
+    stream.funded += harvested;
+    stream.distributed = stream
+        .distributed
+        .checked_add(amount)
+        .map_err(StdError::from)?;
+    if total_staked.is_zero() || config.pause_flags.accrual {
+        stream.undistributed_rewards = stream
+            .undistributed_rewards
+            .checked_add(amount)
+            .map_err(StdError::from)?;
+    } else {
+        distribute(stream, total_staked, amount)?;
+    }
+    Ok(())
+}
+
+/// Withdraws the staking rewards pending on every validator and shares them
+/// like harvested ones. The chain pays them out on any change to a
+/// delegation, so this runs before each to keep them from reaching the
+/// contract unaccounted for.
+fn withdraw_delegation_rewards(
+    deps: Deps,
+    env: &Env,
+    config: &mut Config,
+) -> Result<Vec<DistributionMsg>, ContractError> {
+    let denom = match delegated_denom(config) {
+        Some(denom) => denom.to_string(),
+        None => return Ok(vec![]),
     };
-    execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+    let stream_id = harvest_stream(config, &denom)?;
 
-    // Forward time
-    env.block.time = env.block.time.plus_seconds(86400); // 1 day 
+    let mut msgs = vec![];
+    let mut withdrawn = Uint128::zero();
+    for validator in VALIDATORS.keys(deps.storage, None, None, Order::Ascending) {
+        let validator = validator?;
+        let pending: Uint128 = deps
+            .querier
+            .query_delegation(&env.contract.address, &validator)?
+            .map(|delegation| delegation.accumulated_rewards)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|reward| reward.denom == denom)
+            .map(|reward| reward.amount)
+            .sum();
+        if pending.is_zero() {
+            continue;
+        }
+        withdrawn += pending;
+        msgs.push(DistributionMsg::WithdrawDelegatorReward { validator });
+    }
+    if !withdrawn.is_zero() {
+        share_harvested(config, stream_id, withdrawn)?;
+    }
+    Ok(msgs)
+}
 
-    // Alice claims rewards
-    let info = mock_info("alice", &[]);
-    let res = try_claim(deps.as_mut(), env.clone(), info.clone()).unwrap();
-    assert_eq!(res.attributes[0].value, "claim");
-    assert!(res.attributes.iter().any(|attr| attr.key == "amount" && attr.value != "0"));
+/// Index of the first reward stream paid in `denom`.
+fn harvest_stream(config: &Config, denom: &str) -> Result<usize, ContractError> {
+    config
+        .reward_streams
+        .iter()
+        .position(|stream| stream.asset == AssetInfo::native(denom))
+        .ok_or_else(|| ContractError::NoHarvestStream {
+            denom: denom.to_string(),
+        })
+}
 
-    // Query Alice's state 
-    let res = query(deps.as_mut(), env.clone(), QueryMsg::User { address: "alice".to_string() }).unwrap();
-    let user: UserResponse = from_binary(&res).unwrap();
-    assert_eq!(user.rewards, Uint128::zero());
+/// Denom of the stake delegated to validators, `None` unless
+/// `delegate_stake` is set.
+fn delegated_denom(config: &Config) -> Option<&str> {
+    match &config.stake_asset {
+        AssetInfo::Native { denom } if config.delegate_stake => Some(denom),
+        _ => None,
+    }
 }
-    
+
+/// Validator of the set with the least stake delegated to it.
+fn least_delegated(storage: &dyn Storage) -> Result<(String, Uint128), ContractError> {
+    VALIDATORS
+        .range(storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?
+        .into_iter()
+        .min_by_key(|(_, delegated)| *delegated)
+        .ok_or(ContractError::NoValidators {})
+}
+
+/// Delegates `amount` to the validator with the least stake, keeping the set
+/// balanced.
+fn delegate(
+    storage: &mut dyn Storage,
+    denom: &str,
+    amount: Uint128,
+) -> Result<StakingMsg, ContractError> {
+    let (validator, delegated) = least_delegated(storage)?;
+    VALIDATORS.save(storage, &validator, &(delegated + amount))?;
+    Ok(StakingMsg::Delegate {
+        validator,
+        amount: coin(amount.u128(), denom),
+    })
 }
 
+/// Undelegates `amount` from the validators with the most stake first.
+fn undelegate(
+    storage: &mut dyn Storage,
+    denom: &str,
+    amount: Uint128,
+) -> Result<Vec<StakingMsg>, ContractError> {
+    let mut validators = VALIDATORS
+        .range(storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+    validators.sort_by(|(_, a), (_, b)| b.cmp(a));
+
+    let mut remaining = amount;
+    let mut msgs = vec![];
+    for (validator, delegated) in validators {
+        if remaining.is_zero() {
+            break;
+        }
+        let undelegated = delegated.min(remaining);
+        remaining -= undelegated;
+        VALIDATORS.save(storage, &validator, &(delegated - undelegated))?;
+        msgs.push(StakingMsg::Undelegate {
+            validator,
+            amount: coin(undelegated.u128(), denom),
+        });
+    }
+    Ok(msgs)
+}
+
+/// Fails with `Paused` naming `operation` while its pause flag is set.
+fn assert_not_paused(paused: bool, operation: &str) -> Result<(), ContractError> {
+    if paused {
+        return Err(ContractError::Paused {
+            operation: operation.to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// Fails with `Unauthorized` unless `sender` is the current owner.
+fn assert_owner(deps: Deps, sender: &Addr) -> Result<(), ContractError> {
+    let ownership = OWNERSHIP.load(deps.storage)?;
+    if ownership.owner.as_ref() != Some(sender) {
+        return Err(ContractError::Unauthorized {});
+    }
+    Ok(())
+}
+
+fn format_addr(addr: &Option<Addr>) -> String {
+    addr.as_ref().map_or_else(|| "none".to_string(), Addr::to_string)
+}
+
+fn format_list(items: &[String]) -> String {
+    if items.is_empty() {
+        return "none".to_string();
+    }
+    items.join(",")
+}
+
+fn query_config(deps: Deps) -> StdResult<ConfigResponse> {
+    let config = read_config(deps.storage)?;
+    Ok(ConfigResponse {
+        stake_asset: config.stake_asset,
+        total_value_locked: config.total_value_locked,
+        total_effective_stake: config.total_effective_stake,
+        reward_streams: config.reward_streams,
+        lock_tiers: config.lock_tiers,
+        early_withdrawal_penalty: config.early_withdrawal_penalty,
+    })
+}
+fn query_user(deps: Deps, env: Env, address: String) -> StdResult<UserResponse> {
+    let (config, user) = settled_user(deps, env, address)?;
+    Ok(UserResponse {
+        staked_amount: user.staked_amount,
+        effective_stake: user.effective_stake,
+        locks: user.locks.clone(),
+        rewards: reward_amounts(&config, &user),
+        auto_compound: user.auto_compound,
+    })
+}
+
+fn query_rewards(deps: Deps, env: Env, address: String) -> StdResult<RewardsResponse> {
+    let (config, user) = settled_user(deps, env, address)?;
+    Ok(RewardsResponse {
+        rewards: reward_amounts(&config, &user),
+    })
+}
+
+fn query_simulate_rewards(
+    deps: Deps,
+    env: Env,
+    address: String,
+    at_time: u64,
+    extra_stake: Option<Uint128>,
+) -> StdResult<RewardsResponse> {
+    let now = env.block.time.seconds();
+    if at_time < now {
+        return Err(StdError::generic_err("Cannot simulate rewards in the past"));
+    }
+    let addr = deps.api.addr_validate(&address)?;
+    let mut user = USERS.may_load(deps.storage, &addr)?.unwrap_or_else(|| User {
+        staked_amount: Uint128::zero(),
+        effective_stake: Uint128::zero(),
+        locks: vec![],
+        last_staked_time: now,
+        deposits: vec![],
+        rewards: vec![],
+        auto_compound: false,
+    });
+    let mut config = read_config(deps.storage)?;
+
+    // Works on copies, nothing is saved
+    update_global_state(deps.storage, &mut config, now)?;
+    settle_pending(deps.storage, &mut config, &mut user)?;
+    if let Some(extra_stake) = extra_stake {
+        config.total_value_locked += extra_stake;
+        user.staked_amount += extra_stake;
+        update_effective_stake(&mut config, &mut user);
+    }
+    update_global_state(deps.storage, &mut config, at_time)?;
+    settle_pending(deps.storage, &mut config, &mut user)?;
+    Ok(RewardsResponse {
+        rewards: reward_amounts(&config, &user),
+    })
+}
+
+/// `address`'s position as it would be if settled right now.
+fn settled_user(deps: Deps, env: Env, address: String) -> StdResult<(Config, User)> {
+    let addr = deps.api.addr_validate(&address)?;
+    let mut user = USERS.load(deps.storage, &addr)?;
+    let mut config = read_config(deps.storage)?;
+
+    update_global_state(deps.storage, &mut config, env.block.time.seconds())?;
+    settle_pending(deps.storage, &mut config, &mut user)?;
+    Ok((config, user))
+}
+
+/// Settles `user` without auto-compounding, so queries report the rewards
+/// in the stake asset still pending rather than as already staked.
+fn settle_pending(storage: &dyn Storage, config: &mut Config, user: &mut User) -> StdResult<()> {
+    let auto_compound = std::mem::take(&mut user.auto_compound);
+    settle(storage, config, user)?;
+    user.auto_compound = auto_compound;
+    Ok(())
+}
+
+/// Rewards banked by a settled `user`, per stream.
+fn reward_amounts(config: &Config, user: &User) -> Vec<RewardAmount> {
+    config
+        .reward_streams
+        .iter()
+        .zip(&user.rewards)
+        .enumerate()
+        .map(|(stream_id, (stream, reward))| RewardAmount {
+            stream_id: stream_id as u64,
+            asset: stream.asset.clone(),
+            amount: reward.rewards,
+        })
+        .collect()
+}
+
+fn query_undistributed(deps: Deps, env: Env) -> StdResult<UndistributedResponse> {
+    let mut config = read_config(deps.storage)?;
+    update_global_state(deps.storage, &mut config, env.block.time.seconds())?;
+
+    let rewards = config
+        .reward_streams
+        .into_iter()
+        .enumerate()
+        .map(|(stream_id, stream)| {
+            Ok(RewardAmount {
+                stream_id: stream_id as u64,
+                amount: Uint128::try_from(stream.undistributed_rewards.to_uint_floor())?,
+                asset: stream.asset,
+            })
+        })
+        .collect::<StdResult<_>>()?;
+
+    Ok(UndistributedResponse {
+        policy: config.undistributed_policy,
+        rewards,
+    })
+}
+
+fn query_reward_reserve(deps: Deps, env: Env) -> StdResult<RewardReserveResponse> {
+    let mut config = read_config(deps.storage)?;
+    update_global_state(deps.storage, &mut config, env.block.time.seconds())?;
+
+    let streams = config
+        .reward_streams
+        .into_iter()
+        .enumerate()
+        .map(|(stream_id, stream)| {
+            let rate = emission_rate(&stream, env.block.time.seconds());
+            let runway = if rate.is_zero() {
+                None
+            } else {
+                let runway = (stream.reserve() / rate).to_uint_floor();
+                Some(Uint64::try_from(runway).map_or(u64::MAX, |runway| runway.u64()))
+            };
+            Ok(StreamReserve {
+                stream_id: stream_id as u64,
+                funded: stream.funded,
+                distributed: Uint128::try_from(stream.distributed.to_uint_floor())?,
+                claimed: stream.claimed,
+                runway,
+                asset: stream.asset,
+            })
+        })
+        .collect::<StdResult<_>>()?;
+
+    Ok(RewardReserveResponse { streams })
+}
+
+fn query_campaigns(
+    deps: Deps,
+    env: Env,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<CampaignsResponse> {
+    let config = read_config(deps.storage)?;
+    let now = env.block.time.seconds();
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(Bound::exclusive);
+
+    let past: Vec<CampaignInfo> = PAST_CAMPAIGNS
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| {
+            let (_, (stream_id, campaign)) = item?;
+            Ok(CampaignInfo {
+                stream_id,
+                asset: config.reward_streams[stream_id as usize].asset.clone(),
+                start: campaign.start,
+                end: campaign.end,
+                budget: campaign.budget,
+            })
+        })
+        .collect::<StdResult<_>>()?;
+    // Campaigns still waiting to be archived follow the archive's last page
+    let last_page = past.len() < limit;
+    let mut res = CampaignsResponse {
+        past,
+        active: vec![],
+        upcoming: vec![],
+    };
+    for (stream_id, stream) in config.reward_streams.iter().enumerate() {
+        for campaign in &stream.campaigns {
+            let info = CampaignInfo {
+                stream_id: stream_id as u64,
+                asset: stream.asset.clone(),
+                start: campaign.start,
+                end: campaign.end,
+                budget: campaign.budget,
+            };
+            if campaign.end <= now {
+                if last_page {
+                    res.past.push(info);
+                }
+            } else if campaign.start <= now {
+                res.active.push(info);
+            } else {
+                res.upcoming.push(info);
+            }
+        }
+    }
+    res.upcoming.sort_by_key(|campaign| campaign.start);
+    Ok(res)
+}
+
+fn query_validators(deps: Deps) -> StdResult<ValidatorsResponse> {
+    let validators = VALIDATORS
+        .range(deps.storage, None, None, Order::Ascending)
+        .map(|item| {
+            let (address, delegated) = item?;
+            Ok(ValidatorAllocation { address, delegated })
+        })
+        .collect::<StdResult<_>>()?;
+    Ok(ValidatorsResponse { validators })
+}
+
+fn query_apr(deps: Deps, env: Env) -> StdResult<AprResponse> {
+    let mut config = read_config(deps.storage)?;
+    let now = env.block.time.seconds();
+    update_global_state(deps.storage, &mut config, now)?;
+
+    // Emission is shared by effective stake, what an unlocked token earns
+    let total_staked = Decimal256::from_ratio(config.total_effective_stake, 1u8);
+    let streams = config
+        .reward_streams
+        .into_iter()
+        .enumerate()
+        .map(|(stream_id, stream)| {
+            let mut apr = StreamApr {
+                stream_id: stream_id as u64,
+                asset: stream.asset.clone(),
+                per_second: None,
+                apr: None,
+                apy: None,
+            };
+            if total_staked.is_zero() {
+                return Ok(apr);
+            }
+            // Nothing is emitted while accrual is paused or the reserve is empty
+            let (rate, yearly) = if config.pause_flags.accrual || stream.reserve().is_zero() {
+                (Decimal256::zero(), Decimal256::zero())
+            } else {
+                let yearly = emitted_between(&stream, now, now + YEAR_SECONDS)?;
+                (emission_rate(&stream, now), yearly.min(stream.reserve()))
+            };
+            let yearly_rate = yearly / total_staked;
+            let daily_growth = Decimal256::one() + yearly_rate / Decimal256::from_ratio(365u16, 1u8);
+            let apy = daily_growth
+                .checked_pow(365)
+                .map_or(Decimal256::MAX, |growth| growth - Decimal256::one());
+            apr.per_second = Some(saturating_decimal(rate / total_staked));
+            apr.apr = Some(saturating_decimal(yearly_rate));
+            apr.apy = Some(saturating_decimal(apy));
+            Ok(apr)
+        })
+        .collect::<StdResult<_>>()?;
+
+    Ok(AprResponse { streams })
+}
+
+/// `value` as a `Decimal`, capped at its maximum.
+fn saturating_decimal(value: Decimal256) -> Decimal {
+    Decimal::try_from(value).unwrap_or(Decimal::MAX)
+}
+
+const DEFAULT_LIMIT: u32 = 10;
+const MAX_LIMIT: u32 = 30;
+
+fn query_all_stakers(
+    deps: Deps,
+    env: Env,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<AllStakersResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start_after = start_after
+        .map(|address| deps.api.addr_validate(&address))
+        .transpose()?;
+    let start = start_after.as_ref().map(Bound::exclusive);
+
+    let mut config = read_config(deps.storage)?;
+    update_global_state(deps.storage, &mut config, env.block.time.seconds())?;
+    let stakers = USERS
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| {
+            let (address, mut user) = item?;
+            settle_pending(deps.storage, &mut config, &mut user)?;
+            Ok(StakerInfo {
+                address,
+                staked_amount: user.staked_amount,
+                rewards: reward_amounts(&config, &user),
+            })
+        })
+        .collect::<StdResult<_>>()?;
+    Ok(AllStakersResponse { stakers })
+}
+
+fn query_pause_flags(deps: Deps) -> StdResult<PauseFlagsResponse> {
+    let config = read_config(deps.storage)?;
+    Ok(PauseFlagsResponse {
+        flags: config.pause_flags,
+        guardian: config.guardian,
+    })
+}
+
+fn query_simulate_withdraw(
+    deps: Deps,
+    env: Env,
+    address: String,
+    amount: Uint128,
+) -> StdResult<SimulateWithdrawResponse> {
+    let addr = deps.api.addr_validate(&address)?;
+    let mut user = USERS.load(deps.storage, &addr)?;
+    let config = read_config(deps.storage)?;
+    let penalty = withdraw_deposits(&config, &mut user, amount, env.block.time.seconds());
+    Ok(SimulateWithdrawResponse {
+        penalty,
+        payout: amount - penalty,
+    })
+}
+
+fn query_unbonding(deps: Deps, address: String) -> StdResult<UnbondingResponse> {
+    let addr = deps.api.addr_validate(&address)?;
+    let entries = UNBONDING.may_load(deps.storage, &addr)?.unwrap_or_default();
+    Ok(UnbondingResponse { entries })
+}
+
+/// Seconds in the 30-day period `monthly_reward` is emitted over.
+const MONTH_SECONDS: u64 = 30 * 24 * 60 * 60;
+const YEAR_SECONDS: u64 = 365 * 24 * 60 * 60;
+/// Granularity of lock end times.
+const LOCK_EXPIRY_BUCKET: u64 = 24 * 60 * 60;
+
+/// Advances every stream's `global_exchange_rate`, the cumulative reward paid
+/// per staked token, up to `current_time`.
+///
+/// The `eps` emitted over the elapsed time is shared pro rata between the
+/// effective stake during it, so however often the index is advanced and
+/// however `total_effective_stake` changes in between, the sum of all
+/// stakers' rewards equals what was emitted. Emissions while nothing is
+/// staked are set aside in `undistributed_rewards` and, under
+/// `UndistributedPolicy::Rollover`, added to the index as soon as there are
+/// stakers again. A stream never emits more than its funded reserve and stays
+/// paused until topped up. While accrual is paused the index is frozen and
+/// nothing is emitted. The index is settled at every lock expiry on the way,
+/// the expired boost leaving `total_effective_stake` right after.
+fn update_global_state(
+    storage: &dyn Storage,
+    config: &mut Config,
+    current_time: u64,
+) -> StdResult<()> {
+    let expiries = LOCK_EXPIRIES
+        .range(
+            storage,
+            Some(Bound::exclusive(config.last_update_time)),
+            Some(Bound::inclusive(current_time)),
+            Order::Ascending,
+        )
+        .collect::<StdResult<Vec<_>>>()?;
+    for (unlock_at, mut expiry) in expiries {
+        accrue(config, unlock_at)?;
+        let exchange_rates = config
+            .reward_streams
+            .iter()
+            .map(|stream| stream.global_exchange_rate)
+            .collect();
+        expiry.exchange_rates = Some(exchange_rates);
+        config.total_effective_stake -= expiry.boost;
+        config.passed_lock_expiries.push((unlock_at, expiry));
+    }
+    accrue(config, current_time)
+}
+
+/// Advances the indexes of every stream to `current_time` at the current
+/// `total_effective_stake`.
+fn accrue(config: &mut Config, current_time: u64) -> StdResult<()> {
+    if config.pause_flags.accrual {
+        config.last_update_time = current_time;
+        return Ok(());
+    }
+    let time_elapsed = current_time.saturating_sub(config.last_update_time);
+    let rollover = config.undistributed_policy == UndistributedPolicy::Rollover;
+    for stream in config.reward_streams.iter_mut() {
+        if time_elapsed > 0 {
+            let emitted = emitted_between(stream, config.last_update_time, current_time)?
+                .min(stream.reserve());
+            stream.distributed = stream.distributed.checked_add(emitted)?;
+            if config.total_effective_stake.is_zero() {
+                stream.undistributed_rewards = stream.undistributed_rewards.checked_add(emitted)?;
+            } else {
+                distribute(stream, config.total_effective_stake, emitted)?;
+            }
+        }
+        if rollover
+            && !config.total_effective_stake.is_zero()
+            && !stream.undistributed_rewards.is_zero()
+        {
+            let undistributed = std::mem::take(&mut stream.undistributed_rewards);
+            distribute(stream, config.total_effective_stake, undistributed)?;
+        }
+    }
+    config.last_update_time = current_time;
+    Ok(())
+}
+
+/// Rewards `stream` emits from `from` to `to`: its base `eps` plus each
+/// campaign's budget pro rata to how much of its window falls in between.
+fn emitted_between(stream: &RewardStream, from: u64, to: u64) -> StdResult<Decimal256> {
+    let mut emitted = stream.eps.checked_mul(Decimal256::from_ratio(to - from, 1u64))?;
+    for campaign in &stream.campaigns {
+        let overlap = to.min(campaign.end).saturating_sub(from.max(campaign.start));
+        if overlap > 0 {
+            let share = Uint256::from(campaign.budget) * Uint256::from(overlap);
+            emitted = emitted.checked_add(Decimal256::from_ratio(
+                share,
+                campaign.end - campaign.start,
+            ))?;
+        }
+    }
+    Ok(emitted)
+}
+
+/// Reward `stream` emits per second at `time`.
+fn emission_rate(stream: &RewardStream, time: u64) -> Decimal256 {
+    stream
+        .campaigns
+        .iter()
+        .filter(|campaign| campaign.start <= time && time < campaign.end)
+        .fold(stream.eps, |rate, campaign| {
+            rate + Decimal256::from_ratio(campaign.budget, campaign.end - campaign.start)
+        })
+}
+
+/// Shares `amount` of `stream`'s rewards between the `total_staked` tokens.
+fn distribute(
+    stream: &mut RewardStream,
+    total_staked: Uint128,
+    amount: Decimal256,
+) -> StdResult<()> {
+    let total_staked = Decimal256::from_ratio(total_staked, 1u8);
+    stream.global_exchange_rate = stream
+        .global_exchange_rate
+        .checked_add(amount / total_staked)?;
+    Ok(())
+}
+
+/// Reward emitted per second when `monthly_reward` is spread over 30 days.
+///
+/// All reward math is done in `Decimal256` fixed point with 18 fractional
+/// digits. Each index update truncates at most 1e-18 per staked token, and
+/// payouts are floored to whole units, so stakers are never paid more than
+/// was emitted.
+pub(crate) fn calculate_eps(monthly_reward: Uint128) -> Decimal256 {
+    Decimal256::from_ratio(monthly_reward, MONTH_SECONDS)
+}
+
+/// Banks the rewards `user` accrued on every stream since their last
+/// settlement and moves their snapshots up to the current indexes, locks
+/// expired by `last_update_time` only boosting them up to their `unlock_at`.
+/// With `auto_compound` set and claims not paused the rewards paid in the
+/// stake asset are then staked, returns the amount compounded.
+fn settle(storage: &dyn Storage, config: &mut Config, user: &mut User) -> StdResult<Uint128> {
+    // A stream added since the last settlement started from a zero index
+    user.rewards.resize_with(config.reward_streams.len(), UserReward::default);
+    let now = config.last_update_time;
+    let (mut expired, locks): (Vec<Lock>, Vec<Lock>) = std::mem::take(&mut user.locks)
+        .into_iter()
+        .partition(|lock| lock.unlock_at <= now);
+    user.locks = locks;
+    // Each expired lock boosted the rewards up to its own `unlock_at` only
+    expired.sort_by_key(|lock| lock.unlock_at);
+    for lock in expired.iter().filter(|lock| !lock_boost(lock).is_zero()) {
+        let exchange_rates = lock_expiry(storage, config, lock.unlock_at)?
+            .exchange_rates
+            .unwrap_or_default();
+        for (id, reward) in user.rewards.iter_mut().enumerate() {
+            // Streams added since the lock expired never saw its boost
+            let exchange_rate = exchange_rates.get(id).copied().unwrap_or(reward.exchange_rate);
+            reward.rewards += calculate_rewards(exchange_rate, user.effective_stake, reward)?;
+            reward.exchange_rate = exchange_rate;
+        }
+        user.effective_stake -= lock_boost(lock);
+    }
+    for (stream, reward) in config.reward_streams.iter().zip(user.rewards.iter_mut()) {
+        let exchange_rate = stream.global_exchange_rate;
+        reward.rewards += calculate_rewards(exchange_rate, user.effective_stake, reward)?;
+        reward.exchange_rate = exchange_rate;
+    }
+    let compounded = if user.auto_compound && !config.pause_flags.claim {
+        compound(config, user)
+    } else {
+        Uint128::zero()
+    };
+    update_effective_stake(config, user);
+    Ok(compounded)
+}
+
+/// Effective stake `lock` adds on top of its amount.
+fn lock_boost(lock: &Lock) -> Uint128 {
+    lock.amount.mul_floor(lock.boost) - lock.amount
+}
+
+/// End of a lock running until at least `time`, rounded up to a whole day.
+fn unlock_time(time: u64) -> u64 {
+    time.div_ceil(LOCK_EXPIRY_BUCKET) * LOCK_EXPIRY_BUCKET
+}
+
+/// Adds the boost of a new `lock` to the expiry at its `unlock_at`.
+fn schedule_lock_expiry(storage: &mut dyn Storage, lock: &Lock) -> StdResult<()> {
+    let boost = lock_boost(lock);
+    if boost.is_zero() {
+        return Ok(());
+    }
+    LOCK_EXPIRIES.update(storage, lock.unlock_at, |expiry| -> StdResult<_> {
+        let mut expiry = expiry.unwrap_or_default();
+        expiry.boost += boost;
+        Ok(expiry)
+    })?;
+    Ok(())
+}
+
+/// The expiry at `unlock_at`, as last updated by `config`.
+fn lock_expiry(storage: &dyn Storage, config: &Config, unlock_at: u64) -> StdResult<LockExpiry> {
+    match config
+        .passed_lock_expiries
+        .iter()
+        .find(|(passed, _)| *passed == unlock_at)
+    {
+        Some((_, expiry)) => Ok(expiry.clone()),
+        None => LOCK_EXPIRIES.load(storage, unlock_at),
+    }
+}
+
+/// Recomputes `user`'s stake weighted by the boost of their locks and keeps
+/// `total_effective_stake` in step.
+fn update_effective_stake(config: &mut Config, user: &mut User) {
+    let locked: Uint128 = user.locks.iter().map(|lock| lock.amount).sum();
+    let boosted: Uint128 = user
+        .locks
+        .iter()
+        .map(|lock| lock.amount.mul_floor(lock.boost))
+        .sum();
+    let effective_stake = user.staked_amount - locked + boosted;
+    config.total_effective_stake = config.total_effective_stake - user.effective_stake
+        + effective_stake;
+    user.effective_stake = effective_stake;
+}
+
+/// Moves the settled rewards of `user` paid in the stake asset into their
+/// stake, returns the amount moved.
+fn compound(config: &mut Config, user: &mut User) -> Uint128 {
+    let mut compounded = Uint128::zero();
+    for (stream, reward) in config.reward_streams.iter_mut().zip(user.rewards.iter_mut()) {
+        if stream.asset != config.stake_asset {
+            continue;
+        }
+        let amount = std::mem::take(&mut reward.rewards);
+        stream.claimed += amount;
+        compounded += amount;
+    }
+    user.staked_amount += compounded;
+    config.total_value_locked += compounded;
+    compounded
+}
+
+/// Rewards accrued by `effective_stake` from the `reward` snapshot up to
+/// `exchange_rate`, rounded down.
+fn calculate_rewards(
+    exchange_rate: Decimal256,
+    effective_stake: Uint128,
+    reward: &UserReward,
+) -> StdResult<Uint128> {
+    let exchange_rate_diff = exchange_rate.checked_sub(reward.exchange_rate)?;
+    let rewards = Uint256::from(effective_stake) * exchange_rate_diff;
+    Ok(Uint128::try_from(rewards)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
+    use cosmwasm_std::{coin, coins, from_json, Addr, BankMsg, Coin, CosmosMsg, Timestamp};
+
+    #[test]
+    fn initialization() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+        let info = mock_info("creator", &[]);
+        let msg = InstantiateMsg {
+            monthly_reward: Some(Uint128::new(1000000)),
+            stake_asset: AssetInfo::native("orai"),
+            reward_asset: AssetInfo::native("orai"),
+            undistributed_policy: None,
+            admin: None,
+            unbonding_period: None,
+            delegate_stake: None,
+        };
+    
+        let res = instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+        assert_eq!(0, res.messages.len());
+    
+        let res = query(deps.as_ref(), env, QueryMsg::Configure {}).unwrap();
+        let config: ConfigResponse = from_json(&res).unwrap();
+        assert_eq!(config.reward_streams[0].monthly_reward, Uint128::new(1000000));
+        assert_eq!(config.total_value_locked, Uint128::zero());
+    }
+    
+
+    #[test]
+    fn stake_tokens() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+        let info = mock_info("creator", &[]);
+        let msg = InstantiateMsg {
+            monthly_reward: Some(Uint128::new(1000000)),
+            stake_asset: AssetInfo::native("orai"),
+            reward_asset: AssetInfo::native("orai"),
+            undistributed_policy: None,
+            admin: None,
+            unbonding_period: None,
+            delegate_stake: None,
+        };
+        instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+    
+        let info = mock_info("alice", &coins(100, "orai"));
+        let msg = ExecuteMsg::Stake {};
+        let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+        assert_eq!(res.attributes[0].value, "stake");
+    
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::User { address: "alice".to_string() }).unwrap();
+        let user: UserResponse = from_json(&res).unwrap();
+        assert_eq!(user.staked_amount, Uint128::new(100));
+    }
+    
+
+    #[test]
+    fn stake_without_funds() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+        let info = mock_info("creator", &[]);
+        let msg = InstantiateMsg {
+            monthly_reward: Some(Uint128::new(1000000)),
+            stake_asset: AssetInfo::native("orai"),
+            reward_asset: AssetInfo::native("orai"),
+            undistributed_policy: None,
+            admin: None,
+            unbonding_period: None,
+            delegate_stake: None,
+        };
+        instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+        let info = mock_info("alice", &[]);
+        let err = execute(deps.as_mut(), env, info, ExecuteMsg::Stake {}).unwrap_err();
+        assert_eq!(err, ContractError::NoFunds {});
+    }
+
+    #[test]
+    fn stake_with_extra_funds() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+        let info = mock_info("creator", &[]);
+        let msg = InstantiateMsg {
+            monthly_reward: Some(Uint128::new(1000000)),
+            stake_asset: AssetInfo::native("orai"),
+            reward_asset: AssetInfo::native("orai"),
+            undistributed_policy: None,
+            admin: None,
+            unbonding_period: None,
+            delegate_stake: None,
+        };
+        instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+        let info = mock_info("alice", &[coin(100, "orai"), coin(100, "atom")]);
+        let err = execute(deps.as_mut(), env, info, ExecuteMsg::Stake {}).unwrap_err();
+        assert_eq!(err, ContractError::MultipleDenoms {});
+    }
+
+    #[test]
+    fn stake_with_wrong_denom() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+        let info = mock_info("creator", &[]);
+        let msg = InstantiateMsg {
+            monthly_reward: Some(Uint128::new(1000000)),
+            stake_asset: AssetInfo::native("orai"),
+            reward_asset: AssetInfo::native("orai"),
+            undistributed_policy: None,
+            admin: None,
+            unbonding_period: None,
+            delegate_stake: None,
+        };
+        instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+        let info = mock_info("alice", &coins(100, "atom"));
+        let err = execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Stake {}).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::InvalidDenom {
+                expected: "orai".to_string(),
+                got: "atom".to_string(),
+            }
+        );
+
+        let info = mock_info("alice", &coins(0, "orai"));
+        let err = execute(deps.as_mut(), env, info, ExecuteMsg::Stake {}).unwrap_err();
+        assert_eq!(err, ContractError::InvalidAmount {});
+    }
+
+    #[test]
+    fn withdraw_tokens() {
+        let mut deps = mock_dependencies();
+        let mut env = mock_env();
+        let info = mock_info("creator", &[]);
+        let msg = InstantiateMsg {
+            monthly_reward: Some(Uint128::new(1000000)),
+            stake_asset: AssetInfo::native("orai"),
+            reward_asset: AssetInfo::native("orai"),
+            undistributed_policy: None,
+            admin: None,
+            unbonding_period: None,
+            delegate_stake: None,
+        };
+        instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+        let info = mock_info("creator", &coins(1_000_000_000, "orai"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::FundRewards { stream_id: 0 }).unwrap();
+    
+        let info = mock_info("alice", &coins(100, "orai"));
+        let msg = ExecuteMsg::Stake {};
+        execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+    
+        env.block.time = env.block.time.plus_seconds(86400); // 1 day later
+    
+        let info = mock_info("alice", &[]);
+        let msg = ExecuteMsg::Withdraw {
+            amount: Uint128::new(50),
+        };
+        let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+        assert_eq!(res.attributes[0].value, "withdraw");
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "alice".to_string(),
+                amount: coins(50, "orai"),
+            })
+        );
+    
+        let res = query(deps.as_ref(), env, QueryMsg::User { address: "alice".to_string() }).unwrap();
+        let user: UserResponse = from_json(&res).unwrap();
+        assert_eq!(user.staked_amount, Uint128::new(50));
+    }
+    
+
+    #[test]
+fn two_person_acts() {
+    let mut deps = mock_dependencies();
+    let mut env = mock_env();
+    let info = mock_info("creator", &[]);
+    let msg = InstantiateMsg {
+        monthly_reward: Some(Uint128::new(1000000)),
+        stake_asset: AssetInfo::native("orai"),
+        reward_asset: AssetInfo::native("orai"),
+        undistributed_policy: None,
+        admin: None,
+        unbonding_period: None,
+        delegate_stake: None,
+    };
+    instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+    let info = mock_info("creator", &coins(1_000_000_000, "orai"));
+    execute(deps.as_mut(), env.clone(), info, ExecuteMsg::FundRewards { stream_id: 0 }).unwrap();
+
+    // Alice stakes 100 ORAI
+    let info = mock_info("alice", &coins(100, "orai"));
+    let msg = ExecuteMsg::Stake {};
+    execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    // Bob stakes 200 ORAI
+    let info = mock_info("bob", &coins(200, "orai"));
+    let msg = ExecuteMsg::Stake {};
+    execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    // Forward time
+    env.block.time = env.block.time.plus_seconds(1_296_000);
+    update_global_state(&deps.storage, &mut read_config(&deps.storage).unwrap(), env.block.time.seconds()).unwrap();
+
+    // Bob withdraws half (100 ORAI)
+    let info = mock_info("bob", &[]);
+    let msg = ExecuteMsg::Withdraw {
+        amount: Uint128::new(100),
+    };
+    execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    // Forward time
+    env.block.time = env.block.time.plus_seconds(2_206_000);
+    update_global_state(&deps.storage, &mut read_config(&deps.storage).unwrap(), env.block.time.seconds()).unwrap();
+
+    // Alice withdraws all (100 ORAI)
+    let info = mock_info("alice", &[]);
+    let msg = ExecuteMsg::Withdraw {
+        amount: Uint128::new(100),
+    };
+    execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    // Bob withdraws the rest (100 ORAI)
+    let info = mock_info("bob", &[]);
+    let msg = ExecuteMsg::Withdraw {
+        amount: Uint128::new(100),
+    };
+    execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    // Query Alice
+    let res = query(deps.as_ref(), env.clone(), QueryMsg::User { address: "alice".to_string() }).unwrap();
+    let user: UserResponse = from_json(&res).unwrap();
+    println!("Alice - Staked amount: {}, Rewards: {}", user.staked_amount, user.rewards[0].amount);
+    assert_eq!(user.staked_amount, Uint128::zero());
+    assert!(user.rewards[0].amount > Uint128::zero());
+
+    // Query Bob
+    let res = query(deps.as_ref(), env, QueryMsg::User { address: "bob".to_string() }).unwrap();
+    let user: UserResponse = from_json(&res).unwrap();
+    println!("Bob - Staked amount: {}, Rewards: {}", user.staked_amount, user.rewards[0].amount);
+    assert_eq!(user.staked_amount, Uint128::zero());
+    assert!(user.rewards[0].amount > Uint128::zero());
+}
+
+#[test]
+fn claim_rewards() {
+    let mut deps = mock_dependencies();
+    let mut env = mock_env();
+    let info = mock_info("creator", &[]);
+    let msg = InstantiateMsg {
+        monthly_reward: Some(Uint128::new(1000000)),
+        stake_asset: AssetInfo::native("orai"),
+        reward_asset: AssetInfo::native("orai"),
+        undistributed_policy: None,
+        admin: None,
+        unbonding_period: None,
+        delegate_stake: None,
+    };
+    instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+    let info = mock_info("creator", &coins(1_000_000_000, "orai"));
+    execute(deps.as_mut(), env.clone(), info, ExecuteMsg::FundRewards { stream_id: 0 }).unwrap();
+
+    // Alice stakes 100 ORAI
+    let info = mock_info("alice", &coins(100, "orai"));
+    let msg = ExecuteMsg::Stake {};
+    execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    // Forward time
+    env.block.time = env.block.time.plus_seconds(86400); // 1 day 
+
+    // Alice claims rewards
+    let info = mock_info("alice", &[]);
+    let res = try_claim(deps.as_mut(), env.clone(), info.clone()).unwrap();
+    assert_eq!(res.attributes[0].value, "claim");
+    assert!(res.attributes.iter().any(|attr| attr.key == "amount" && attr.value != "0"));
+
+    let paid = res.attributes.iter().find(|attr| attr.key == "amount").unwrap().value.clone();
+    assert_eq!(
+        res.messages[0].msg,
+        CosmosMsg::Bank(BankMsg::Send {
+            to_address: "alice".to_string(),
+            amount: vec![Coin {
+                denom: "orai".to_string(),
+                amount: paid.parse().unwrap(),
+            }],
+        })
+    );
+
+    // Query Alice's state 
+    let res = query(deps.as_ref(), env.clone(), QueryMsg::User { address: "alice".to_string() }).unwrap();
+    let user: UserResponse = from_json(&res).unwrap();
+    assert_eq!(user.rewards[0].amount, Uint128::zero());
+
+    // Nothing left to claim in the same block
+    let err = try_claim(deps.as_mut(), env, info).unwrap_err();
+    assert_eq!(err, ContractError::InvalidClaim {});
+}
+
+#[test]
+fn claim_includes_banked_rewards() {
+    let mut deps = mock_dependencies();
+    let mut env = mock_env();
+    let info = mock_info("creator", &[]);
+    let msg = InstantiateMsg {
+        monthly_reward: Some(Uint128::new(1000000)),
+        stake_asset: AssetInfo::native("orai"),
+        reward_asset: AssetInfo::native("orai"),
+        undistributed_policy: None,
+        admin: None,
+        unbonding_period: None,
+        delegate_stake: None,
+    };
+    instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+    let info = mock_info("creator", &coins(1_000_000_000, "orai"));
+    execute(deps.as_mut(), env.clone(), info, ExecuteMsg::FundRewards { stream_id: 0 }).unwrap();
+
+    let info = mock_info("alice", &coins(100, "orai"));
+    execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Stake {}).unwrap();
+
+    env.block.time = env.block.time.plus_seconds(86400);
+
+    // Staking again banks the rewards accrued so far into `User.rewards`
+    let info = mock_info("alice", &coins(100, "orai"));
+    execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Stake {}).unwrap();
+    let banked = USERS.load(&deps.storage, &Addr::unchecked("alice")).unwrap().rewards[0].rewards;
+    assert!(!banked.is_zero());
+
+    // Claiming in the same block pays the banked rewards even though nothing new accrued
+    let info = mock_info("alice", &[]);
+    let res = execute(deps.as_mut(), env, info, ExecuteMsg::Claim {}).unwrap();
+    assert_eq!(
+        res.messages[0].msg,
+        CosmosMsg::Bank(BankMsg::Send {
+            to_address: "alice".to_string(),
+            amount: vec![Coin {
+                denom: "orai".to_string(),
+                amount: banked,
+            }],
+        })
+    );
+    assert!(res.attributes.iter().any(|attr| attr.key == "amount" && attr.value == banked.to_string()));
+    let user = USERS.load(&deps.storage, &Addr::unchecked("alice")).unwrap();
+    assert_eq!(user.rewards[0].rewards, Uint128::zero());
+}
+    
+
+    #[test]
+    fn month_of_emissions_paid_within_one_unit_per_staker() {
+        let mut deps = mock_dependencies();
+        let mut env = mock_env();
+        let monthly_reward = Uint128::new(1_000_000_007);
+        let info = mock_info("creator", &[]);
+        let msg = InstantiateMsg {
+            monthly_reward: Some(monthly_reward),
+            stake_asset: AssetInfo::native("orai"),
+            reward_asset: AssetInfo::native("orai"),
+            undistributed_policy: None,
+            admin: None,
+            unbonding_period: None,
+            delegate_stake: None,
+        };
+        instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+        let info = mock_info("creator", &coins(monthly_reward.u128(), "orai"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::FundRewards { stream_id: 0 }).unwrap();
+
+        let stakers = [("alice", 7u128), ("bob", 1_234_567), ("carol", 987_654_321)];
+        for (staker, amount) in stakers {
+            let info = mock_info(staker, &coins(amount, "orai"));
+            execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Stake {}).unwrap();
+        }
+
+        // Settle part-way through the month so the index is advanced more than once
+        env.block.time = env.block.time.plus_seconds(MONTH_SECONDS / 3);
+        let info = mock_info("bob", &[]);
+        let res = execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Claim {}).unwrap();
+        let claimed: Uint128 = res
+            .attributes
+            .iter()
+            .find(|attr| attr.key == "amount")
+            .unwrap()
+            .value
+            .parse()
+            .unwrap();
+
+        env.block.time = env.block.time.plus_seconds(MONTH_SECONDS - MONTH_SECONDS / 3);
+        let mut paid = claimed;
+        for (staker, _) in stakers {
+            let res = query(
+                deps.as_ref(),
+                env.clone(),
+                QueryMsg::User {
+                    address: staker.to_string(),
+                },
+            )
+            .unwrap();
+            let user: UserResponse = from_json(&res).unwrap();
+            paid += user.rewards[0].amount;
+        }
+
+        assert!(paid <= monthly_reward);
+        assert!(monthly_reward - paid <= Uint128::new(stakers.len() as u128));
+    }
+
+    #[test]
+    fn rewards_conserved_across_tvl_changes() {
+        let mut deps = mock_dependencies();
+        let mut env = mock_env();
+        let monthly_reward = Uint128::new(2_592_000_000);
+        let info = mock_info("creator", &[]);
+        let msg = InstantiateMsg {
+            monthly_reward: Some(monthly_reward),
+            stake_asset: AssetInfo::native("orai"),
+            reward_asset: AssetInfo::native("orai"),
+            undistributed_policy: None,
+            admin: None,
+            unbonding_period: None,
+            delegate_stake: None,
+        };
+        instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+        let info = mock_info("creator", &coins(monthly_reward.u128(), "orai"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::FundRewards { stream_id: 0 }).unwrap();
+
+        let day = 24 * 60 * 60;
+        let withdraw = |amount: u128| ExecuteMsg::Withdraw {
+            amount: Uint128::new(amount),
+        };
+        let actions = [
+            (0, mock_info("alice", &coins(100, "orai")), ExecuteMsg::Stake {}),
+            (3 * day, mock_info("bob", &coins(300, "orai")), ExecuteMsg::Stake {}),
+            (5 * day, mock_info("alice", &[]), withdraw(50)),
+            (7 * day, mock_info("carol", &coins(1_000, "orai")), ExecuteMsg::Stake {}),
+            (11 * day, mock_info("bob", &[]), ExecuteMsg::Claim {}),
+            (13 * day, mock_info("bob", &[]), withdraw(300)),
+            (17 * day, mock_info("alice", &coins(25, "orai")), ExecuteMsg::Stake {}),
+            (23 * day, mock_info("carol", &[]), withdraw(999)),
+        ];
+
+        let settlements = actions.len() + 3;
+        let start = env.block.time;
+        let mut claimed = Uint128::zero();
+        for (offset, info, msg) in actions {
+            env.block.time = start.plus_seconds(offset);
+            let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+            if res.attributes[0].value == "claim" {
+                claimed += res.attributes[1].value.parse::<Uint128>().unwrap();
+            }
+        }
+
+        env.block.time = start.plus_seconds(MONTH_SECONDS);
+        let mut paid = claimed;
+        for staker in ["alice", "bob", "carol"] {
+            let res = query(
+                deps.as_ref(),
+                env.clone(),
+                QueryMsg::User {
+                    address: staker.to_string(),
+                },
+            )
+            .unwrap();
+            let user: UserResponse = from_json(&res).unwrap();
+            paid += user.rewards[0].amount;
+        }
+
+        // Every second of the month had stakers, so the whole emission is owed,
+        // less under one unit of rounding per settlement
+        assert!(paid <= monthly_reward);
+        assert!(monthly_reward - paid <= Uint128::new(settlements as u128));
+    }
+
+    #[test]
+    fn undistributed_rewards_roll_over_to_next_staker() {
+        let mut deps = mock_dependencies();
+        let mut env = mock_env();
+        let info = mock_info("creator", &[]);
+        let msg = InstantiateMsg {
+            monthly_reward: Some(Uint128::new(2_592_000)),
+            stake_asset: AssetInfo::native("orai"),
+            reward_asset: AssetInfo::native("orai"),
+            undistributed_policy: Some(UndistributedPolicy::Rollover),
+            admin: None,
+            unbonding_period: None,
+            delegate_stake: None,
+        };
+        instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+        let info = mock_info("creator", &coins(1_000_000_000, "orai"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::FundRewards { stream_id: 0 }).unwrap();
+
+        // 1 token per second is emitted with nobody staked
+        env.block.time = env.block.time.plus_seconds(1_000);
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::Undistributed {}).unwrap();
+        let undistributed: UndistributedResponse = from_json(&res).unwrap();
+        assert_eq!(undistributed.policy, UndistributedPolicy::Rollover);
+        assert_eq!(undistributed.rewards[0].amount, Uint128::new(1_000));
+
+        let info = mock_info("alice", &coins(100, "orai"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Stake {}).unwrap();
+
+        // The first staker picks up the backlog along with new emissions
+        env.block.time = env.block.time.plus_seconds(10);
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::User { address: "alice".to_string() }).unwrap();
+        let user: UserResponse = from_json(&res).unwrap();
+        assert_eq!(user.rewards[0].amount, Uint128::new(1_010));
+
+        let res = query(deps.as_ref(), env, QueryMsg::Undistributed {}).unwrap();
+        let undistributed: UndistributedResponse = from_json(&res).unwrap();
+        assert_eq!(undistributed.rewards[0].amount, Uint128::zero());
+    }
+
+    #[test]
+    fn undistributed_rewards_kept_for_reclaim() {
+        let mut deps = mock_dependencies();
+        let mut env = mock_env();
+        let info = mock_info("creator", &[]);
+        let msg = InstantiateMsg {
+            monthly_reward: Some(Uint128::new(2_592_000)),
+            stake_asset: AssetInfo::native("orai"),
+            reward_asset: AssetInfo::native("orai"),
+            undistributed_policy: Some(UndistributedPolicy::Reclaim),
+            admin: None,
+            unbonding_period: None,
+            delegate_stake: None,
+        };
+        instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+        let info = mock_info("creator", &coins(1_000_000_000, "orai"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::FundRewards { stream_id: 0 }).unwrap();
+
+        env.block.time = env.block.time.plus_seconds(1_000);
+        let info = mock_info("alice", &coins(100, "orai"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Stake {}).unwrap();
+
+        // Alice only earns what was emitted while she was staked
+        env.block.time = env.block.time.plus_seconds(10);
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::User { address: "alice".to_string() }).unwrap();
+        let user: UserResponse = from_json(&res).unwrap();
+        assert_eq!(user.rewards[0].amount, Uint128::new(10));
+
+        let res = query(deps.as_ref(), env, QueryMsg::Undistributed {}).unwrap();
+        let undistributed: UndistributedResponse = from_json(&res).unwrap();
+        assert_eq!(undistributed.policy, UndistributedPolicy::Reclaim);
+        assert_eq!(undistributed.rewards[0].amount, Uint128::new(1_000));
+    }
+
+    #[test]
+    fn ownership_transfer() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+        let info = mock_info("creator", &[]);
+        let msg = InstantiateMsg {
+            monthly_reward: Some(Uint128::new(1000000)),
+            stake_asset: AssetInfo::native("orai"),
+            reward_asset: AssetInfo::native("orai"),
+            undistributed_policy: None,
+            admin: Some("admin".to_string()),
+            unbonding_period: None,
+            delegate_stake: None,
+        };
+        instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::Ownership {}).unwrap();
+        let ownership: Ownership = from_json(&res).unwrap();
+        assert_eq!(ownership.owner, Some(Addr::unchecked("admin")));
+
+        // Only the owner can propose a new one
+        let transfer = ExecuteMsg::UpdateOwnership(OwnershipAction::TransferOwnership {
+            new_owner: "bob".to_string(),
+        });
+        let err = execute(deps.as_mut(), env.clone(), mock_info("creator", &[]), transfer.clone())
+            .unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized {});
+        execute(deps.as_mut(), env.clone(), mock_info("admin", &[]), transfer).unwrap();
+
+        // Only the proposed owner can accept
+        let accept = ExecuteMsg::UpdateOwnership(OwnershipAction::AcceptOwnership);
+        let err = execute(deps.as_mut(), env.clone(), mock_info("admin", &[]), accept.clone())
+            .unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized {});
+        execute(deps.as_mut(), env.clone(), mock_info("bob", &[]), accept).unwrap();
+
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::Ownership {}).unwrap();
+        let ownership: Ownership = from_json(&res).unwrap();
+        assert_eq!(
+            ownership,
+            Ownership {
+                owner: Some(Addr::unchecked("bob")),
+                pending_owner: None,
+            }
+        );
+
+        let renounce = ExecuteMsg::UpdateOwnership(OwnershipAction::RenounceOwnership);
+        let err = execute(deps.as_mut(), env.clone(), mock_info("admin", &[]), renounce.clone())
+            .unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized {});
+        execute(deps.as_mut(), env.clone(), mock_info("bob", &[]), renounce).unwrap();
+
+        let res = query(deps.as_ref(), env, QueryMsg::Ownership {}).unwrap();
+        let ownership: Ownership = from_json(&res).unwrap();
+        assert_eq!(ownership.owner, None);
+    }
+
+    #[test]
+    fn owner_reclaims_undistributed_rewards() {
+        let mut deps = mock_dependencies();
+        let mut env = mock_env();
+        let info = mock_info("creator", &[]);
+        let msg = InstantiateMsg {
+            monthly_reward: Some(Uint128::new(2_592_000)),
+            stake_asset: AssetInfo::native("orai"),
+            reward_asset: AssetInfo::native("orai"),
+            undistributed_policy: Some(UndistributedPolicy::Reclaim),
+            admin: None,
+            unbonding_period: None,
+            delegate_stake: None,
+        };
+        instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+        let info = mock_info("creator", &coins(1_000_000_000, "orai"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::FundRewards { stream_id: 0 }).unwrap();
+
+        env.block.time = env.block.time.plus_seconds(1_000);
+
+        let err = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("alice", &[]),
+            ExecuteMsg::ReclaimUndistributed {},
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized {});
+
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("creator", &[]),
+            ExecuteMsg::ReclaimUndistributed {},
+        )
+        .unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "creator".to_string(),
+                amount: coins(1_000, "orai"),
+            })
+        );
+
+        let res = query(deps.as_ref(), env, QueryMsg::Undistributed {}).unwrap();
+        let undistributed: UndistributedResponse = from_json(&res).unwrap();
+        assert_eq!(undistributed.rewards[0].amount, Uint128::zero());
+    }
+
+    #[test]
+    fn update_config_keeps_accrued_rewards() {
+        let mut deps = mock_dependencies();
+        let mut env = mock_env();
+        let info = mock_info("creator", &[]);
+        let msg = InstantiateMsg {
+            monthly_reward: Some(Uint128::new(2_592_000)),
+            stake_asset: AssetInfo::native("orai"),
+            reward_asset: AssetInfo::native("orai"),
+            undistributed_policy: None,
+            admin: None,
+            unbonding_period: None,
+            delegate_stake: None,
+        };
+        instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+        let info = mock_info("creator", &coins(1_000_000_000, "orai"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::FundRewards { stream_id: 0 }).unwrap();
+
+        let info = mock_info("alice", &coins(100, "orai"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Stake {}).unwrap();
+        let info = mock_info("bob", &coins(300, "orai"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Stake {}).unwrap();
+
+        // 1 token per second for 1000 seconds
+        env.block.time = env.block.time.plus_seconds(1_000);
+
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::User { address: "alice".to_string() }).unwrap();
+        let user: UserResponse = from_json(&res).unwrap();
+        assert_eq!(user.rewards[0].amount, Uint128::new(250));
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::User { address: "bob".to_string() }).unwrap();
+        let user: UserResponse = from_json(&res).unwrap();
+        assert_eq!(user.rewards[0].amount, Uint128::new(750));
+
+        let update = ExecuteMsg::UpdateConfig(UpdateConfigMsg {
+            monthly_reward: Some(Uint128::new(5_184_000)),
+            ..UpdateConfigMsg::default()
+        });
+        let err = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), update.clone())
+            .unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized {});
+
+        let res = execute(deps.as_mut(), env.clone(), mock_info("creator", &[]), update).unwrap();
+        assert!(res
+            .attributes
+            .iter()
+            .any(|attr| attr.key == "old_monthly_reward" && attr.value == "2592000"));
+        assert!(res
+            .attributes
+            .iter()
+            .any(|attr| attr.key == "new_monthly_reward" && attr.value == "5184000"));
+
+        // Then 2 tokens per second for another 1000 seconds
+        env.block.time = env.block.time.plus_seconds(1_000);
+
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::User { address: "alice".to_string() }).unwrap();
+        let user: UserResponse = from_json(&res).unwrap();
+        assert_eq!(user.rewards[0].amount, Uint128::new(250 + 500));
+
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::User { address: "bob".to_string() }).unwrap();
+        let user: UserResponse = from_json(&res).unwrap();
+        assert_eq!(user.rewards[0].amount, Uint128::new(750 + 1_500));
+
+        let res = query(deps.as_ref(), env, QueryMsg::Configure {}).unwrap();
+        let config: ConfigResponse = from_json(&res).unwrap();
+        assert_eq!(config.reward_streams[0].monthly_reward, Uint128::new(5_184_000));
+        assert_eq!(config.reward_streams[0].eps, Decimal256::from_ratio(2u8, 1u8));
+    }
+
+    #[test]
+    fn update_reward_stream_keeps_accrued_rewards() {
+        let mut deps = mock_dependencies();
+        let mut env = mock_env();
+        let info = mock_info("creator", &[]);
+        let msg = InstantiateMsg {
+            monthly_reward: Some(Uint128::new(2_592_000)),
+            stake_asset: AssetInfo::native("orai"),
+            reward_asset: AssetInfo::native("orai"),
+            undistributed_policy: None,
+            admin: None,
+            unbonding_period: None,
+            delegate_stake: None,
+        };
+        instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+        let info = mock_info("creator", &coins(1_000_000_000, "orai"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::FundRewards { stream_id: 0 }).unwrap();
+
+        let info = mock_info("alice", &coins(100, "orai"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Stake {}).unwrap();
+        let info = mock_info("bob", &coins(300, "orai"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Stake {}).unwrap();
+
+        // 1 token per second for 1000 seconds
+        env.block.time = env.block.time.plus_seconds(1_000);
+
+        let update = ExecuteMsg::UpdateRewardStream {
+            stream_id: 0,
+            monthly_reward: Uint128::new(5_184_000),
+        };
+        let err = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), update.clone())
+            .unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized {});
+
+        let res = execute(deps.as_mut(), env.clone(), mock_info("creator", &[]), update).unwrap();
+        assert!(res
+            .attributes
+            .iter()
+            .any(|attr| attr.key == "old_monthly_reward" && attr.value == "2592000"));
+        assert!(res
+            .attributes
+            .iter()
+            .any(|attr| attr.key == "new_monthly_reward" && attr.value == "5184000"));
+
+        // Then 2 tokens per second for another 1000 seconds
+        env.block.time = env.block.time.plus_seconds(1_000);
+
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::User { address: "alice".to_string() }).unwrap();
+        let user: UserResponse = from_json(&res).unwrap();
+        assert_eq!(user.rewards[0].amount, Uint128::new(250 + 500));
+
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::User { address: "bob".to_string() }).unwrap();
+        let user: UserResponse = from_json(&res).unwrap();
+        assert_eq!(user.rewards[0].amount, Uint128::new(750 + 1_500));
+
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::Configure {}).unwrap();
+        let config: ConfigResponse = from_json(&res).unwrap();
+        assert_eq!(config.reward_streams[0].monthly_reward, Uint128::new(5_184_000));
+        assert_eq!(config.reward_streams[0].eps, Decimal256::from_ratio(2u8, 1u8));
+
+        // UpdateConfig still sets the rate of stream 0
+        let update = ExecuteMsg::UpdateConfig(UpdateConfigMsg {
+            monthly_reward: Some(Uint128::new(2_592_000)),
+            ..UpdateConfigMsg::default()
+        });
+        execute(deps.as_mut(), env.clone(), mock_info("creator", &[]), update).unwrap();
+        let res = query(deps.as_ref(), env, QueryMsg::Configure {}).unwrap();
+        let config: ConfigResponse = from_json(&res).unwrap();
+        assert_eq!(config.reward_streams[0].eps, Decimal256::one());
+    }
+
+    #[test]
+    fn migrate_from_legacy_state() {
+        use crate::migrations::v0_1;
+
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+        let legacy_config = v0_1::Config {
+            monthly_reward: coin(1_000_000, "orai"),
+            total_value_locked: coin(300, "orai"),
+            eps: Uint128::new(3858),
+            last_update_time: env.block.time.seconds() - 86_400,
+            global_exchange_rate: Uint128::new(5_000_000),
+        };
+        v0_1::CONFIG.save(&mut deps.storage, &legacy_config).unwrap();
+        let legacy_user = v0_1::User {
+            staked_amount: coin(300, "orai"),
+            exchange_rate: Uint128::new(1_000_000),
+            last_staked_time: env.block.time.seconds() - 86_400,
+            rewards: Uint128::new(42),
+        };
+        let alice = Addr::unchecked("alice");
+        v0_1::USERS.save(&mut deps.storage, &alice, &legacy_user).unwrap();
+
+        let msg = MigrateMsg {
+            owner: Some("admin".to_string()),
+        };
+        let res = migrate(deps.as_mut(), env.clone(), msg).unwrap();
+        assert!(res
+            .attributes
+            .iter()
+            .any(|attr| attr.key == "previous_version" && attr.value == "none"));
+
+        let version = cw2::get_contract_version(&deps.storage).unwrap();
+        assert_eq!(version.contract, CONTRACT_NAME);
+        assert_eq!(version.version, CONTRACT_VERSION);
+
+        let config = read_config(&deps.storage).unwrap();
+        assert_eq!(config.total_value_locked, Uint128::new(300));
+        assert_eq!(
+            config.reward_streams,
+            vec![RewardStream::new(
+                AssetInfo::native("orai"),
+                Uint128::new(1_000_000)
+            )]
+        );
+        assert_eq!(config.last_update_time, env.block.time.seconds());
+
+        let user = USERS.load(&deps.storage, &alice).unwrap();
+        assert_eq!(user.staked_amount, Uint128::new(300));
+        assert_eq!(
+            user.rewards,
+            vec![UserReward {
+                exchange_rate: Decimal256::zero(),
+                // 42 banked plus 300 * (5e6 - 1e6) / 1e6 pending
+                rewards: Uint128::new(1_242),
+            }]
+        );
+
+        let ownership = OWNERSHIP.load(&deps.storage).unwrap();
+        assert_eq!(ownership.owner, Some(Addr::unchecked("admin")));
+    }
+
+    #[test]
+    fn migrate_from_legacy_state_needs_owner() {
+        use crate::migrations::v0_1;
+
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+        let legacy_config = v0_1::Config {
+            monthly_reward: coin(1_000_000, "orai"),
+            total_value_locked: coin(0, "orai"),
+            eps: Uint128::zero(),
+            last_update_time: env.block.time.seconds(),
+            global_exchange_rate: Uint128::zero(),
+        };
+        v0_1::CONFIG.save(&mut deps.storage, &legacy_config).unwrap();
+
+        let err = migrate(deps.as_mut(), env, MigrateMsg { owner: None }).unwrap_err();
+        assert_eq!(err, ContractError::MissingOwner {});
+        assert!(cw2::get_contract_version(&deps.storage).is_err());
+    }
+
+    #[test]
+    fn migrate_refuses_downgrade_and_other_contracts() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+        let info = mock_info("creator", &[]);
+        let msg = InstantiateMsg {
+            monthly_reward: Some(Uint128::new(1000000)),
+            stake_asset: AssetInfo::native("orai"),
+            reward_asset: AssetInfo::native("orai"),
+            undistributed_policy: None,
+            admin: None,
+            unbonding_period: None,
+            delegate_stake: None,
+        };
+        instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+        // Same version is a no-op upgrade
+        let res = migrate(deps.as_mut(), env.clone(), MigrateMsg { owner: None }).unwrap();
+        assert!(res
+            .attributes
+            .iter()
+            .any(|attr| attr.key == "previous_version" && attr.value == CONTRACT_VERSION));
+        let ownership = OWNERSHIP.load(&deps.storage).unwrap();
+        assert_eq!(ownership.owner, Some(Addr::unchecked("creator")));
+
+        set_contract_version(&mut deps.storage, CONTRACT_NAME, "999.0.0").unwrap();
+        let err = migrate(deps.as_mut(), env.clone(), MigrateMsg { owner: None }).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::CannotDowngrade {
+                previous_version: "999.0.0".to_string(),
+            }
+        );
+
+        set_contract_version(&mut deps.storage, "crates.io:cw20-base", "0.1.0").unwrap();
+        let err = migrate(deps.as_mut(), env, MigrateMsg { owner: None }).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::InvalidMigration {
+                previous_contract: "crates.io:cw20-base".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn unbonding_stake_stops_earning() {
+        let mut deps = mock_dependencies();
+        let mut env = mock_env();
+        let info = mock_info("creator", &[]);
+        let msg = InstantiateMsg {
+            monthly_reward: Some(Uint128::new(2_592_000)),
+            stake_asset: AssetInfo::native("orai"),
+            reward_asset: AssetInfo::native("orai"),
+            undistributed_policy: None,
+            admin: None,
+            unbonding_period: Some(86_400),
+            delegate_stake: None,
+        };
+        instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+        let info = mock_info("creator", &coins(1_000_000_000, "orai"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::FundRewards { stream_id: 0 }).unwrap();
+
+        let info = mock_info("alice", &coins(100, "orai"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Stake {}).unwrap();
+        let info = mock_info("bob", &coins(100, "orai"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Stake {}).unwrap();
+
+        // Bob starts unbonding everything; no funds move yet
+        let info = mock_info("bob", &[]);
+        let msg = ExecuteMsg::Withdraw {
+            amount: Uint128::new(100),
+        };
+        let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+        assert!(res.messages.is_empty());
+
+        // Alice now earns the whole emission
+        env.block.time = env.block.time.plus_seconds(1_000);
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::User { address: "alice".to_string() }).unwrap();
+        let user: UserResponse = from_json(&res).unwrap();
+        assert_eq!(user.rewards[0].amount, Uint128::new(1_000));
+        let res = query(deps.as_ref(), env, QueryMsg::User { address: "bob".to_string() }).unwrap();
+        let user: UserResponse = from_json(&res).unwrap();
+        assert_eq!(user.rewards[0].amount, Uint128::zero());
+    }
+
+    #[test]
+    fn reward_streams_accrue_and_claim_together() {
+        let mut deps = mock_dependencies();
+        let mut env = mock_env();
+        let info = mock_info("creator", &[]);
+        let msg = InstantiateMsg {
+            monthly_reward: Some(Uint128::new(2_592_000)),
+            stake_asset: AssetInfo::native("orai"),
+            reward_asset: AssetInfo::native("orai"),
+            undistributed_policy: None,
+            admin: None,
+            unbonding_period: None,
+            delegate_stake: None,
+        };
+        instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+        let info = mock_info("creator", &coins(1_000_000_000, "orai"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::FundRewards { stream_id: 0 }).unwrap();
+
+        let info = mock_info("alice", &coins(100, "orai"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Stake {}).unwrap();
+        env.block.time = env.block.time.plus_seconds(1_000);
+
+        // A partner stream of 2 usdc per second, earned only from now on
+        let add = ExecuteMsg::AddRewardStream {
+            asset: AssetInfo::native("usdc"),
+            monthly_reward: Uint128::new(5_184_000),
+        };
+        let err = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), add.clone())
+            .unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized {});
+        execute(deps.as_mut(), env.clone(), mock_info("creator", &[]), add).unwrap();
+        let info = mock_info("partner", &coins(1_000_000_000, "usdc"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::FundRewards { stream_id: 1 }).unwrap();
+
+        let update = ExecuteMsg::UpdateRewardStream {
+            stream_id: 2,
+            monthly_reward: Uint128::zero(),
+        };
+        let err = execute(deps.as_mut(), env.clone(), mock_info("creator", &[]), update)
+            .unwrap_err();
+        assert_eq!(err, ContractError::UnknownRewardStream { stream_id: 2 });
+
+        env.block.time = env.block.time.plus_seconds(1_000);
+
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::Rewards { address: "alice".to_string() }).unwrap();
+        let rewards: RewardsResponse = from_json(&res).unwrap();
+        assert_eq!(
+            rewards.rewards,
+            vec![
+                RewardAmount {
+                    stream_id: 0,
+                    asset: AssetInfo::native("orai"),
+                    amount: Uint128::new(2_000),
+                },
+                RewardAmount {
+                    stream_id: 1,
+                    asset: AssetInfo::native("usdc"),
+                    amount: Uint128::new(2_000),
+                },
+            ]
+        );
+
+        let res = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), ExecuteMsg::Claim {}).unwrap();
+        assert_eq!(
+            res.messages.into_iter().map(|msg| msg.msg).collect::<Vec<_>>(),
+            vec![
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: "alice".to_string(),
+                    amount: coins(2_000, "orai"),
+                }),
+                CosmosMsg::Bank(BankMsg::Send {
+                    to_address: "alice".to_string(),
+                    amount: coins(2_000, "usdc"),
+                }),
+            ]
+        );
+
+        let res = query(deps.as_ref(), env, QueryMsg::Rewards { address: "alice".to_string() }).unwrap();
+        let rewards: RewardsResponse = from_json(&res).unwrap();
+        assert!(rewards.rewards.iter().all(|reward| reward.amount.is_zero()));
+    }
+
+    #[test]
+    fn emission_pauses_when_reserve_runs_out() {
+        let mut deps = mock_dependencies();
+        let mut env = mock_env();
+        let info = mock_info("creator", &[]);
+        let msg = InstantiateMsg {
+            monthly_reward: Some(Uint128::new(2_592_000)),
+            stake_asset: AssetInfo::native("orai"),
+            reward_asset: AssetInfo::native("orai"),
+            undistributed_policy: None,
+            admin: None,
+            unbonding_period: None,
+            delegate_stake: None,
+        };
+        instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+        let info = mock_info("creator", &coins(1_000, "usdc"));
+        let err = execute(deps.as_mut(), env.clone(), info, ExecuteMsg::FundRewards { stream_id: 0 }).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::InvalidDenom {
+                expected: "orai".to_string(),
+                got: "usdc".to_string(),
+            }
+        );
+        let info = mock_info("creator", &coins(1_000, "orai"));
+        let err = execute(deps.as_mut(), env.clone(), info, ExecuteMsg::FundRewards { stream_id: 1 }).unwrap_err();
+        assert_eq!(err, ContractError::UnknownRewardStream { stream_id: 1 });
+
+        // 1 token per second, funded for 1000 seconds
+        let info = mock_info("creator", &coins(1_000, "orai"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::FundRewards { stream_id: 0 }).unwrap();
+        let info = mock_info("alice", &coins(100, "orai"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Stake {}).unwrap();
+
+        env.block.time = env.block.time.plus_seconds(1_500);
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::Rewards { address: "alice".to_string() }).unwrap();
+        let rewards: RewardsResponse = from_json(&res).unwrap();
+        assert_eq!(rewards.rewards[0].amount, Uint128::new(1_000));
+
+        // Topping up resumes emission from now, not for the paused stretch
+        let info = mock_info("partner", &coins(500, "orai"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::FundRewards { stream_id: 0 }).unwrap();
+        env.block.time = env.block.time.plus_seconds(100);
+
+        let res = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), ExecuteMsg::Claim {}).unwrap();
+        let claimed = res.attributes.iter().find(|attr| attr.key == "amount").unwrap();
+        assert_eq!(claimed.value, "1100");
+
+        let res = query(deps.as_ref(), env, QueryMsg::RewardReserve {}).unwrap();
+        let reserve: RewardReserveResponse = from_json(&res).unwrap();
+        assert_eq!(
+            reserve.streams,
+            vec![StreamReserve {
+                stream_id: 0,
+                asset: AssetInfo::native("orai"),
+                funded: Uint128::new(1_500),
+                distributed: Uint128::new(1_100),
+                claimed: Uint128::new(1_100),
+                runway: Some(400),
+            }]
+        );
+    }
+
+    #[test]
+    fn campaign_emits_budget_within_window() {
+        let mut deps = mock_dependencies();
+        let mut env = mock_env();
+        let start = env.block.time.seconds();
+        let info = mock_info("creator", &[]);
+        let msg = InstantiateMsg {
+            monthly_reward: None,
+            stake_asset: AssetInfo::native("orai"),
+            reward_asset: AssetInfo::native("orai"),
+            undistributed_policy: None,
+            admin: None,
+            unbonding_period: None,
+            delegate_stake: None,
+        };
+        instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+        let info = mock_info("creator", &coins(1_000_000_000, "orai"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::FundRewards { stream_id: 0 }).unwrap();
+        let info = mock_info("alice", &coins(100, "orai"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Stake {}).unwrap();
+
+        let schedule = |start: u64, end: u64, budget: u128| ExecuteMsg::ScheduleCampaign {
+            stream_id: 0,
+            start,
+            end,
+            budget: Uint128::new(budget),
+        };
+        let campaigns = |start_after: Option<u64>| QueryMsg::Campaigns {
+            start_after,
+            limit: Some(1),
+        };
+        let msg = schedule(start - 1, start + 100, 1_000);
+        let err = execute(deps.as_mut(), env.clone(), mock_info("creator", &[]), msg).unwrap_err();
+        assert_eq!(err, ContractError::InvalidCampaign {});
+        let msg = schedule(start + 100, start + 1_100, 1_000);
+        let err = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), msg).unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized {});
+        let msg = schedule(start + 5_000, start + 6_000, 500);
+        execute(deps.as_mut(), env.clone(), mock_info("creator", &[]), msg).unwrap();
+        let msg = schedule(start + 100, start + 1_100, 1_000);
+        execute(deps.as_mut(), env.clone(), mock_info("creator", &[]), msg).unwrap();
+
+        let campaign = |start: u64, end: u64, budget: u128| CampaignInfo {
+            stream_id: 0,
+            asset: AssetInfo::native("orai"),
+            start,
+            end,
+            budget: Uint128::new(budget),
+        };
+        let res = query(deps.as_ref(), env.clone(), campaigns(None)).unwrap();
+        let listed: CampaignsResponse = from_json(&res).unwrap();
+        assert_eq!(
+            listed.upcoming,
+            vec![
+                campaign(start + 100, start + 1_100, 1_000),
+                campaign(start + 5_000, start + 6_000, 500),
+            ]
+        );
+
+        // Halfway through the window
+        env.block.time = env.block.time.plus_seconds(600);
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::Rewards { address: "alice".to_string() }).unwrap();
+        let rewards: RewardsResponse = from_json(&res).unwrap();
+        assert_eq!(rewards.rewards[0].amount, Uint128::new(500));
+        let res = query(deps.as_ref(), env.clone(), campaigns(None)).unwrap();
+        let listed: CampaignsResponse = from_json(&res).unwrap();
+        assert_eq!(listed.active, vec![campaign(start + 100, start + 1_100, 1_000)]);
+
+        // Nothing accrues after the window closes
+        env.block.time = env.block.time.plus_seconds(1_400);
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::Rewards { address: "alice".to_string() }).unwrap();
+        let rewards: RewardsResponse = from_json(&res).unwrap();
+        assert_eq!(rewards.rewards[0].amount, Uint128::new(1_000));
+        let res = query(deps.as_ref(), env.clone(), campaigns(None)).unwrap();
+        let listed: CampaignsResponse = from_json(&res).unwrap();
+        assert_eq!(listed.past, vec![campaign(start + 100, start + 1_100, 1_000)]);
+        assert!(listed.active.is_empty());
+        assert_eq!(listed.upcoming, vec![campaign(start + 5_000, start + 6_000, 500)]);
+
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::RewardReserve {}).unwrap();
+        let reserve: RewardReserveResponse = from_json(&res).unwrap();
+        assert_eq!(reserve.streams[0].runway, None);
+
+        // The ended campaign is archived once accrued, the rewards stay owed
+        let info = mock_info("alice", &coins(100, "orai"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Stake {}).unwrap();
+        let config = read_config(&deps.storage).unwrap();
+        assert_eq!(
+            config.reward_streams[0].campaigns,
+            vec![Campaign {
+                start: start + 5_000,
+                end: start + 6_000,
+                budget: Uint128::new(500),
+            }]
+        );
+        let res = query(deps.as_ref(), env.clone(), campaigns(None)).unwrap();
+        let listed: CampaignsResponse = from_json(&res).unwrap();
+        assert_eq!(listed.past, vec![campaign(start + 100, start + 1_100, 1_000)]);
+
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::Rewards { address: "alice".to_string() }).unwrap();
+        let rewards: RewardsResponse = from_json(&res).unwrap();
+        assert_eq!(rewards.rewards[0].amount, Uint128::new(1_000));
+
+        // And stays listed after the next one ends, a page at a time
+        env.block.time = env.block.time.plus_seconds(6_000);
+        let info = mock_info("alice", &coins(100, "orai"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Stake {}).unwrap();
+        assert!(read_config(&deps.storage).unwrap().reward_streams[0].campaigns.is_empty());
+        let res = query(deps.as_ref(), env.clone(), campaigns(None)).unwrap();
+        let listed: CampaignsResponse = from_json(&res).unwrap();
+        assert_eq!(listed.past, vec![campaign(start + 100, start + 1_100, 1_000)]);
+        let res = query(deps.as_ref(), env.clone(), campaigns(Some(0))).unwrap();
+        let listed: CampaignsResponse = from_json(&res).unwrap();
+        assert_eq!(listed.past, vec![campaign(start + 5_000, start + 6_000, 500)]);
+    }
+
+    #[test]
+    fn delegation_needs_native_stake_and_unbonding() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+        let msg = InstantiateMsg {
+            monthly_reward: Some(Uint128::new(1000000)),
+            stake_asset: AssetInfo::native("orai"),
+            reward_asset: AssetInfo::native("orai"),
+            undistributed_policy: None,
+            admin: None,
+            unbonding_period: None,
+            delegate_stake: Some(true),
+        };
+        let info = mock_info("creator", &[]);
+        let err = instantiate(deps.as_mut(), env.clone(), info, msg.clone()).unwrap_err();
+        assert_eq!(err, ContractError::InvalidDelegationConfig {});
+
+        let cw20 = InstantiateMsg {
+            stake_asset: AssetInfo::Cw20 {
+                contract_addr: Addr::unchecked("token"),
+            },
+            unbonding_period: Some(86_400),
+            ..msg.clone()
+        };
+        let info = mock_info("creator", &[]);
+        let err = instantiate(deps.as_mut(), env.clone(), info, cw20).unwrap_err();
+        assert_eq!(err, ContractError::InvalidDelegationConfig {});
+
+        let msg = InstantiateMsg {
+            unbonding_period: Some(86_400),
+            ..msg
+        };
+        // Staking rewards need a stream paid in the bonded denom
+        let atom = InstantiateMsg {
+            reward_asset: AssetInfo::native("atom"),
+            ..msg.clone()
+        };
+        let err = instantiate(deps.as_mut(), env.clone(), mock_info("creator", &[]), atom)
+            .unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::NoHarvestStream {
+                denom: "orai".to_string()
+            }
+        );
+        instantiate(deps.as_mut(), env.clone(), mock_info("creator", &[]), msg).unwrap();
+
+        // Delegated stake cannot be paid out instantly
+        let update = ExecuteMsg::UpdateConfig(UpdateConfigMsg {
+            unbonding_period: Some(0),
+            ..UpdateConfigMsg::default()
+        });
+        let info = mock_info("creator", &[]);
+        let err = execute(deps.as_mut(), env.clone(), info, update).unwrap_err();
+        assert_eq!(err, ContractError::InvalidDelegationConfig {});
+
+        // No validator to delegate to yet
+        let info = mock_info("alice", &coins(100, "orai"));
+        let err = execute(deps.as_mut(), env, info, ExecuteMsg::Stake {}).unwrap_err();
+        assert_eq!(err, ContractError::NoValidators {});
+    }
+
+    #[test]
+    fn compound_moves_rewards_into_stake() {
+        let mut deps = mock_dependencies();
+        let mut env = mock_env();
+        let info = mock_info("creator", &[]);
+        let msg = InstantiateMsg {
+            monthly_reward: Some(Uint128::new(2_592_000)),
+            stake_asset: AssetInfo::native("orai"),
+            reward_asset: AssetInfo::native("orai"),
+            undistributed_policy: None,
+            admin: None,
+            unbonding_period: None,
+            delegate_stake: None,
+        };
+        instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+        let info = mock_info("creator", &coins(1_000_000_000, "orai"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::FundRewards { stream_id: 0 }).unwrap();
+
+        for staker in ["alice", "bob"] {
+            let info = mock_info(staker, &coins(100, "orai"));
+            execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Stake {}).unwrap();
+        }
+        env.block.time = env.block.time.plus_seconds(1_000);
+
+        // Alice compounds once, Bob opts in and has his 500 compounded straight away
+        let info = mock_info("alice", &[]);
+        let res = execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Compound {}).unwrap();
+        assert!(res.messages.is_empty());
+        assert!(res
+            .attributes
+            .iter()
+            .any(|attr| attr.key == "compounded" && attr.value == "500"));
+        let msg = ExecuteMsg::SetAutoCompound { enabled: true };
+        execute(deps.as_mut(), env.clone(), mock_info("bob", &[]), msg).unwrap();
+
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::Configure {}).unwrap();
+        let config: ConfigResponse = from_json(&res).unwrap();
+        assert_eq!(config.total_value_locked, Uint128::new(1_200));
+        assert_eq!(config.reward_streams[0].claimed, Uint128::new(1_000));
+
+        // Each now holds half the pool, 1000 / 1200 per token is rounded down
+        env.block.time = env.block.time.plus_seconds(1_000);
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::User { address: "alice".to_string() }).unwrap();
+        let user: UserResponse = from_json(&res).unwrap();
+        assert_eq!(user.staked_amount, Uint128::new(600));
+        assert_eq!(user.rewards[0].amount, Uint128::new(499));
+        assert!(!user.auto_compound);
+        // Bob's are reported pending until he is next settled
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::User { address: "bob".to_string() }).unwrap();
+        let user: UserResponse = from_json(&res).unwrap();
+        assert_eq!(user.staked_amount, Uint128::new(600));
+        assert_eq!(user.rewards[0].amount, Uint128::new(499));
+        assert!(user.auto_compound);
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::Rewards { address: "bob".to_string() }).unwrap();
+        let rewards: RewardsResponse = from_json(&res).unwrap();
+        assert_eq!(rewards.rewards[0].amount, Uint128::new(499));
+        let simulate = QueryMsg::SimulateRewards {
+            address: "bob".to_string(),
+            at_time: env.block.time.seconds(),
+            extra_stake: None,
+        };
+        let res = query(deps.as_ref(), env.clone(), simulate).unwrap();
+        let rewards: RewardsResponse = from_json(&res).unwrap();
+        assert_eq!(rewards.rewards[0].amount, Uint128::new(499));
+
+        // Bob's compounded stake can be withdrawn like any other
+        let msg = ExecuteMsg::Withdraw {
+            amount: Uint128::new(1_099),
+        };
+        let res = execute(deps.as_mut(), env, mock_info("bob", &[]), msg).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "bob".to_string(),
+                amount: coins(1_099, "orai"),
+            })
+        );
+    }
+
+    #[test]
+    fn claim_pause_holds_back_auto_compounding() {
+        let mut deps = mock_dependencies();
+        let mut env = mock_env();
+        let info = mock_info("creator", &[]);
+        let msg = InstantiateMsg {
+            monthly_reward: Some(Uint128::new(2_592_000)),
+            stake_asset: AssetInfo::native("orai"),
+            reward_asset: AssetInfo::native("orai"),
+            undistributed_policy: None,
+            admin: None,
+            unbonding_period: None,
+            delegate_stake: None,
+        };
+        instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+        let info = mock_info("creator", &coins(1_000_000_000, "orai"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::FundRewards { stream_id: 0 }).unwrap();
+
+        for staker in ["alice", "bob"] {
+            let info = mock_info(staker, &coins(100, "orai"));
+            execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Stake {}).unwrap();
+        }
+        let msg = ExecuteMsg::SetAutoCompound { enabled: true };
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), msg).unwrap();
+        let pause = ExecuteMsg::SetPauseFlags {
+            stake: None,
+            withdraw: None,
+            claim: Some(true),
+            accrual: None,
+        };
+        execute(deps.as_mut(), env.clone(), mock_info("creator", &[]), pause).unwrap();
+        env.block.time = env.block.time.plus_seconds(1_000);
+
+        // Opting in can no longer move rewards into stake
+        let msg = ExecuteMsg::SetAutoCompound { enabled: true };
+        let err = execute(deps.as_mut(), env.clone(), mock_info("bob", &[]), msg).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::Paused {
+                operation: "claim".to_string()
+            }
+        );
+
+        // Alice is settled on withdrawal but her 500 stay banked
+        let msg = ExecuteMsg::Withdraw {
+            amount: Uint128::new(50),
+        };
+        let res = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), msg).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "alice".to_string(),
+                amount: coins(50, "orai"),
+            })
+        );
+        assert!(!res.attributes.iter().any(|attr| attr.key == "compounded"));
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::User { address: "alice".to_string() }).unwrap();
+        let user: UserResponse = from_json(&res).unwrap();
+        assert_eq!(user.staked_amount, Uint128::new(50));
+        assert_eq!(user.rewards[0].amount, Uint128::new(500));
+        let res = query(deps.as_ref(), env, QueryMsg::Configure {}).unwrap();
+        let config: ConfigResponse = from_json(&res).unwrap();
+        assert_eq!(config.total_value_locked, Uint128::new(150));
+        assert_eq!(config.reward_streams[0].claimed, Uint128::zero());
+    }
+
+    #[test]
+    fn compound_needs_stream_paid_in_stake_asset() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+        let info = mock_info("creator", &[]);
+        let msg = InstantiateMsg {
+            monthly_reward: Some(Uint128::new(2_592_000)),
+            stake_asset: AssetInfo::native("orai"),
+            reward_asset: AssetInfo::native("usdc"),
+            undistributed_policy: None,
+            admin: None,
+            unbonding_period: None,
+            delegate_stake: None,
+        };
+        instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+        let info = mock_info("alice", &coins(100, "orai"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Stake {}).unwrap();
+
+        let info = mock_info("alice", &[]);
+        let err = execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Compound {}).unwrap_err();
+        assert_eq!(err, ContractError::NoCompoundStream {});
+        let msg = ExecuteMsg::SetAutoCompound { enabled: true };
+        let err = execute(deps.as_mut(), env, mock_info("alice", &[]), msg).unwrap_err();
+        assert_eq!(err, ContractError::NoCompoundStream {});
+    }
+
+    #[test]
+    fn locked_stake_earns_boost_until_unlocked() {
+        let mut deps = mock_dependencies();
+        let mut env = mock_env();
+        let info = mock_info("creator", &[]);
+        let msg = InstantiateMsg {
+            monthly_reward: Some(Uint128::new(2_592_000)),
+            stake_asset: AssetInfo::native("orai"),
+            reward_asset: AssetInfo::native("orai"),
+            undistributed_policy: None,
+            admin: None,
+            unbonding_period: None,
+            delegate_stake: None,
+        };
+        instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+        let info = mock_info("creator", &coins(1_000_000_000, "orai"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::FundRewards { stream_id: 0 }).unwrap();
+
+        let tiers = |boost: u64| ExecuteMsg::SetLockTiers {
+            tiers: vec![LockTier {
+                duration: 30 * 86_400,
+                boost: Decimal::percent(boost),
+            }],
+        };
+        let err = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), tiers(200))
+            .unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized {});
+        let err = execute(deps.as_mut(), env.clone(), mock_info("creator", &[]), tiers(90))
+            .unwrap_err();
+        assert_eq!(err, ContractError::InvalidLockTier {});
+        execute(deps.as_mut(), env.clone(), mock_info("creator", &[]), tiers(200)).unwrap();
+
+        let info = mock_info("alice", &coins(100, "orai"));
+        let err = execute(deps.as_mut(), env.clone(), info.clone(), ExecuteMsg::StakeLocked { tier: 1 })
+            .unwrap_err();
+        assert_eq!(err, ContractError::UnknownLockTier { tier: 1 });
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::StakeLocked { tier: 0 }).unwrap();
+        let info = mock_info("bob", &coins(100, "orai"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Stake {}).unwrap();
+        // Rounded up to a whole day
+        let unlock_at = (env.block.time.seconds() / 86_400 + 31) * 86_400;
+
+        // Alice's doubled weight earns her two thirds of the emission
+        env.block.time = env.block.time.plus_seconds(300);
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::User { address: "alice".to_string() }).unwrap();
+        let user: UserResponse = from_json(&res).unwrap();
+        assert_eq!(user.effective_stake, Uint128::new(200));
+        assert_eq!(user.rewards[0].amount, Uint128::new(200));
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::User { address: "bob".to_string() }).unwrap();
+        let user: UserResponse = from_json(&res).unwrap();
+        assert_eq!(user.rewards[0].amount, Uint128::new(100));
+
+        let withdraw = ExecuteMsg::Withdraw {
+            amount: Uint128::new(50),
+        };
+        let err = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), withdraw.clone())
+            .unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::StakeLocked {
+                available: Uint128::zero(),
+                unlock_at,
+            }
+        );
+
+        // Once the lock expires the stake is released and weighs one to one
+        env.block.time = Timestamp::from_seconds(unlock_at);
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), withdraw).unwrap();
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::User { address: "alice".to_string() }).unwrap();
+        let user: UserResponse = from_json(&res).unwrap();
+        assert!(user.locks.is_empty());
+        assert_eq!(user.effective_stake, Uint128::new(50));
+        let res = query(deps.as_ref(), env, QueryMsg::Configure {}).unwrap();
+        let config: ConfigResponse = from_json(&res).unwrap();
+        assert_eq!(config.total_effective_stake, Uint128::new(150));
+    }
+
+    #[test]
+    fn early_withdrawal_penalty_decays_and_is_redistributed() {
+        let mut deps = mock_dependencies();
+        let mut env = mock_env();
+        let info = mock_info("creator", &[]);
+        let msg = InstantiateMsg {
+            monthly_reward: Some(Uint128::new(2_592_000)),
+            stake_asset: AssetInfo::native("orai"),
+            reward_asset: AssetInfo::native("orai"),
+            undistributed_policy: None,
+            admin: None,
+            unbonding_period: None,
+            delegate_stake: None,
+        };
+        instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+        let info = mock_info("creator", &coins(1_000_000_000, "orai"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::FundRewards { stream_id: 0 }).unwrap();
+
+        let penalty = |rate: u64, treasury: Option<&str>| {
+            ExecuteMsg::UpdateConfig(UpdateConfigMsg {
+                early_withdrawal_penalty: Some(EarlyWithdrawalPenalty {
+                    rate: Decimal::percent(rate),
+                    period: 10 * 86_400,
+                    treasury: treasury.map(Addr::unchecked),
+                }),
+                ..UpdateConfigMsg::default()
+            })
+        };
+        let info = mock_info("creator", &[]);
+        let err = execute(deps.as_mut(), env.clone(), info.clone(), penalty(150, None)).unwrap_err();
+        assert_eq!(err, ContractError::InvalidPenalty {});
+        execute(deps.as_mut(), env.clone(), info, penalty(50, None)).unwrap();
+
+        for staker in ["alice", "bob"] {
+            let info = mock_info(staker, &coins(100, "orai"));
+            execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Stake {}).unwrap();
+        }
+
+        // Half way through the period half of the 50% rate is left
+        env.block.time = env.block.time.plus_seconds(5 * 86_400);
+        let simulate = QueryMsg::SimulateWithdraw {
+            address: "alice".to_string(),
+            amount: Uint128::new(100),
+        };
+        let res = query(deps.as_ref(), env.clone(), simulate).unwrap();
+        let simulated: SimulateWithdrawResponse = from_json(&res).unwrap();
+        assert_eq!(simulated.penalty, Uint128::new(25));
+        assert_eq!(simulated.payout, Uint128::new(75));
+
+        let msg = ExecuteMsg::Withdraw {
+            amount: Uint128::new(100),
+        };
+        let res = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), msg.clone()).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "alice".to_string(),
+                amount: coins(75, "orai"),
+            })
+        );
+        assert!(res
+            .attributes
+            .iter()
+            .any(|attr| attr.key == "penalty" && attr.value == "25"));
+
+        // Bob alone receives the penalty on top of his half of the emission
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::User { address: "alice".to_string() }).unwrap();
+        let user: UserResponse = from_json(&res).unwrap();
+        assert_eq!(user.rewards[0].amount, Uint128::new(216_000));
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::User { address: "bob".to_string() }).unwrap();
+        let user: UserResponse = from_json(&res).unwrap();
+        assert_eq!(user.rewards[0].amount, Uint128::new(216_025));
+
+        // Sent to a treasury the penalty is paid out alongside the withdrawal
+        let info = mock_info("creator", &[]);
+        execute(deps.as_mut(), env.clone(), info, penalty(50, Some("treasury"))).unwrap();
+        let res = execute(deps.as_mut(), env, mock_info("bob", &[]), msg).unwrap();
+        assert_eq!(
+            res.messages[1].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "treasury".to_string(),
+                amount: coins(25, "orai"),
+            })
+        );
+    }
+
+    #[test]
+    fn pause_flags_halt_operations_and_freeze_accrual() {
+        let mut deps = mock_dependencies();
+        let mut env = mock_env();
+        let info = mock_info("creator", &[]);
+        let msg = InstantiateMsg {
+            monthly_reward: Some(Uint128::new(2_592_000)),
+            stake_asset: AssetInfo::native("orai"),
+            reward_asset: AssetInfo::native("orai"),
+            undistributed_policy: None,
+            admin: None,
+            unbonding_period: None,
+            delegate_stake: None,
+        };
+        instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+        let info = mock_info("creator", &coins(1_000_000_000, "orai"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::FundRewards { stream_id: 0 }).unwrap();
+        let msg = ExecuteMsg::SetGuardian {
+            guardian: Some("guardian".to_string()),
+        };
+        execute(deps.as_mut(), env.clone(), mock_info("creator", &[]), msg).unwrap();
+
+        let info = mock_info("alice", &coins(100, "orai"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Stake {}).unwrap();
+
+        let pause = |stake: Option<bool>, accrual: Option<bool>| ExecuteMsg::SetPauseFlags {
+            stake,
+            withdraw: None,
+            claim: None,
+            accrual,
+        };
+        let err = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), pause(Some(true), None))
+            .unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized {});
+
+        // The guardian halts staking and accrual, but cannot resume them
+        env.block.time = env.block.time.plus_seconds(100);
+        let info = mock_info("guardian", &[]);
+        execute(deps.as_mut(), env.clone(), info.clone(), pause(Some(true), Some(true))).unwrap();
+        let err = execute(deps.as_mut(), env.clone(), info, pause(None, Some(false))).unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized {});
+
+        let info = mock_info("bob", &coins(100, "orai"));
+        let err = execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Stake {}).unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::Paused {
+                operation: "stake".to_string()
+            }
+        );
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::PauseFlags {}).unwrap();
+        let flags: PauseFlagsResponse = from_json(&res).unwrap();
+        assert_eq!(
+            flags.flags,
+            PauseFlags {
+                stake: true,
+                withdraw: false,
+                claim: false,
+                accrual: true,
+            }
+        );
+        assert_eq!(flags.guardian, Some(Addr::unchecked("guardian")));
+
+        // Nothing accrues over the 300 seconds paused
+        env.block.time = env.block.time.plus_seconds(300);
+        let info = mock_info("creator", &[]);
+        execute(deps.as_mut(), env.clone(), info, pause(Some(false), Some(false))).unwrap();
+        env.block.time = env.block.time.plus_seconds(100);
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::User { address: "alice".to_string() }).unwrap();
+        let user: UserResponse = from_json(&res).unwrap();
+        assert_eq!(user.rewards[0].amount, Uint128::new(200));
+
+        let info = mock_info("bob", &coins(100, "orai"));
+        execute(deps.as_mut(), env, info, ExecuteMsg::Stake {}).unwrap();
+    }
+
+    #[test]
+    fn emergency_withdraw_forfeits_rewards_while_paused() {
+        let mut deps = mock_dependencies();
+        let mut env = mock_env();
+        let info = mock_info("creator", &[]);
+        let msg = InstantiateMsg {
+            monthly_reward: Some(Uint128::new(2_592_000)),
+            stake_asset: AssetInfo::native("orai"),
+            reward_asset: AssetInfo::native("orai"),
+            undistributed_policy: None,
+            admin: None,
+            unbonding_period: None,
+            delegate_stake: None,
+        };
+        instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+        let info = mock_info("creator", &coins(1_000_000_000, "orai"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::FundRewards { stream_id: 0 }).unwrap();
+
+        // Alice banks 100 by topping up, then everything is halted
+        let info = mock_info("alice", &coins(100, "orai"));
+        execute(deps.as_mut(), env.clone(), info.clone(), ExecuteMsg::Stake {}).unwrap();
+        env.block.time = env.block.time.plus_seconds(100);
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Stake {}).unwrap();
+        let pause = ExecuteMsg::SetPauseFlags {
+            stake: Some(true),
+            withdraw: Some(true),
+            claim: Some(true),
+            accrual: Some(true),
+        };
+        execute(deps.as_mut(), env.clone(), mock_info("creator", &[]), pause).unwrap();
+
+        env.block.time = env.block.time.plus_seconds(100);
+        let info = mock_info("alice", &[]);
+        let res = execute(deps.as_mut(), env.clone(), info, ExecuteMsg::EmergencyWithdraw {}).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "alice".to_string(),
+                amount: coins(200, "orai"),
+            })
+        );
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::User { address: "alice".to_string() });
+        assert!(res.is_err());
+
+        // Her banked rewards are back in the pool and nothing is staked
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::Undistributed {}).unwrap();
+        let undistributed: UndistributedResponse = from_json(&res).unwrap();
+        assert_eq!(undistributed.rewards[0].amount, Uint128::new(100));
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::Configure {}).unwrap();
+        let config: ConfigResponse = from_json(&res).unwrap();
+        assert_eq!(config.total_value_locked, Uint128::zero());
+        assert_eq!(config.total_effective_stake, Uint128::zero());
+
+        let err = execute(deps.as_mut(), env, mock_info("alice", &[]), ExecuteMsg::EmergencyWithdraw {})
+            .unwrap_err();
+        assert!(matches!(err, ContractError::Std(StdError::NotFound { .. })));
+    }
+
+    #[test]
+    fn all_stakers_paginates_by_address() {
+        let mut deps = mock_dependencies();
+        let mut env = mock_env();
+        let info = mock_info("creator", &[]);
+        let msg = InstantiateMsg {
+            monthly_reward: Some(Uint128::new(2_592_000)),
+            stake_asset: AssetInfo::native("orai"),
+            reward_asset: AssetInfo::native("orai"),
+            undistributed_policy: None,
+            admin: None,
+            unbonding_period: None,
+            delegate_stake: None,
+        };
+        instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+        let info = mock_info("creator", &coins(1_000_000_000, "orai"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::FundRewards { stream_id: 0 }).unwrap();
+
+        for staker in ["carol", "alice", "bob"] {
+            let info = mock_info(staker, &coins(100, "orai"));
+            execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Stake {}).unwrap();
+        }
+        env.block.time = env.block.time.plus_seconds(300);
+
+        let page = |start_after: Option<&str>| QueryMsg::AllStakers {
+            start_after: start_after.map(str::to_string),
+            limit: Some(2),
+        };
+        let res = query(deps.as_ref(), env.clone(), page(None)).unwrap();
+        let first: AllStakersResponse = from_json(&res).unwrap();
+        let addresses: Vec<_> = first.stakers.iter().map(|staker| staker.address.as_str()).collect();
+        assert_eq!(addresses, ["alice", "bob"]);
+        assert_eq!(first.stakers[0].staked_amount, Uint128::new(100));
+        assert_eq!(first.stakers[0].rewards[0].amount, Uint128::new(100));
+
+        let res = query(deps.as_ref(), env, page(Some("bob"))).unwrap();
+        let second: AllStakersResponse = from_json(&res).unwrap();
+        assert_eq!(second.stakers.len(), 1);
+        assert_eq!(second.stakers[0].address, Addr::unchecked("carol"));
+        assert_eq!(second.stakers[0].rewards[0].amount, Uint128::new(100));
+    }
+
+    #[test]
+    fn simulate_rewards_projects_without_saving() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+        let info = mock_info("creator", &[]);
+        let msg = InstantiateMsg {
+            monthly_reward: Some(Uint128::new(2_592_000)),
+            stake_asset: AssetInfo::native("orai"),
+            reward_asset: AssetInfo::native("orai"),
+            undistributed_policy: None,
+            admin: None,
+            unbonding_period: None,
+            delegate_stake: None,
+        };
+        instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+        let info = mock_info("creator", &coins(1_000_000_000, "orai"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::FundRewards { stream_id: 0 }).unwrap();
+        let info = mock_info("alice", &coins(100, "orai"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Stake {}).unwrap();
+
+        let now = env.block.time.seconds();
+        let simulate = |address: &str, at_time: u64, extra_stake: Option<u128>| {
+            QueryMsg::SimulateRewards {
+                address: address.to_string(),
+                at_time,
+                extra_stake: extra_stake.map(Uint128::new),
+            }
+        };
+        let res = query(deps.as_ref(), env.clone(), simulate("alice", now + 300, None)).unwrap();
+        let rewards: RewardsResponse = from_json(&res).unwrap();
+        assert_eq!(rewards.rewards[0].amount, Uint128::new(300));
+
+        // Bob would take half of the emission by staking as much as Alice
+        let res = query(deps.as_ref(), env.clone(), simulate("bob", now + 300, Some(100))).unwrap();
+        let rewards: RewardsResponse = from_json(&res).unwrap();
+        assert_eq!(rewards.rewards[0].amount, Uint128::new(150));
+        let res = query(deps.as_ref(), env.clone(), simulate("alice", now + 300, None)).unwrap();
+        let rewards: RewardsResponse = from_json(&res).unwrap();
+        assert_eq!(rewards.rewards[0].amount, Uint128::new(300));
+
+        let err = query(deps.as_ref(), env, simulate("alice", now - 1, None)).unwrap_err();
+        assert_eq!(err, StdError::generic_err("Cannot simulate rewards in the past"));
+    }
+
+    #[test]
+    fn apr_from_emission_and_reserve() {
+        let mut deps = mock_dependencies();
+        let env = mock_env();
+        let info = mock_info("creator", &[]);
+        let msg = InstantiateMsg {
+            monthly_reward: Some(Uint128::new(2_592_000)),
+            stake_asset: AssetInfo::native("orai"),
+            reward_asset: AssetInfo::native("orai"),
+            undistributed_policy: None,
+            admin: None,
+            unbonding_period: None,
+            delegate_stake: None,
+        };
+        instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+        // Nothing staked, no yield to speak of
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::Apr {}).unwrap();
+        let apr: AprResponse = from_json(&res).unwrap();
+        assert_eq!(apr.streams[0].apr, None);
+
+        let info = mock_info("alice", &coins(31_536_000, "orai"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Stake {}).unwrap();
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::Apr {}).unwrap();
+        let apr: AprResponse = from_json(&res).unwrap();
+        assert_eq!(apr.streams[0].per_second, Some(Decimal::zero()));
+        assert_eq!(apr.streams[0].apr, Some(Decimal::zero()));
+
+        // A year of emission is 1 per staked token, half of it once the
+        // reserve only covers half a year
+        let info = mock_info("creator", &coins(1_000_000_000, "orai"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::FundRewards { stream_id: 0 }).unwrap();
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::Apr {}).unwrap();
+        let apr: AprResponse = from_json(&res).unwrap();
+        assert_eq!(apr.streams[0].per_second, Some(Decimal::from_ratio(1u64, 31_536_000u64)));
+        assert_eq!(apr.streams[0].apr, Some(Decimal::one()));
+        let apy = apr.streams[0].apy.unwrap();
+        assert!(apy > Decimal::percent(171) && apy < Decimal::percent(172));
+
+        let mut deps = mock_dependencies();
+        let info = mock_info("creator", &[]);
+        let msg = InstantiateMsg {
+            monthly_reward: Some(Uint128::new(2_592_000)),
+            stake_asset: AssetInfo::native("orai"),
+            reward_asset: AssetInfo::native("orai"),
+            undistributed_policy: None,
+            admin: None,
+            unbonding_period: None,
+            delegate_stake: None,
+        };
+        instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+        let info = mock_info("creator", &coins(15_768_000, "orai"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::FundRewards { stream_id: 0 }).unwrap();
+        let info = mock_info("alice", &coins(31_536_000, "orai"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Stake {}).unwrap();
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::Apr {}).unwrap();
+        let apr: AprResponse = from_json(&res).unwrap();
+        assert_eq!(apr.streams[0].apr, Some(Decimal::percent(50)));
+
+        // Locked stake takes a boosted share, leaving less for unlocked tokens
+        let tiers = ExecuteMsg::SetLockTiers {
+            tiers: vec![LockTier {
+                duration: 2 * YEAR_SECONDS,
+                boost: Decimal::percent(200),
+            }],
+        };
+        execute(deps.as_mut(), env.clone(), mock_info("creator", &[]), tiers).unwrap();
+        let info = mock_info("bob", &coins(15_768_000, "orai"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::StakeLocked { tier: 0 }).unwrap();
+        let res = query(deps.as_ref(), env, QueryMsg::Apr {}).unwrap();
+        let apr: AprResponse = from_json(&res).unwrap();
+        assert_eq!(apr.streams[0].apr, Some(Decimal::percent(25)));
+    }
+
+    #[test]
+    fn lock_stops_boosting_at_unlock_time() {
+        let mut deps = mock_dependencies();
+        let mut env = mock_env();
+        let info = mock_info("creator", &[]);
+        let msg = InstantiateMsg {
+            monthly_reward: Some(Uint128::new(2_592_000)),
+            stake_asset: AssetInfo::native("orai"),
+            reward_asset: AssetInfo::native("orai"),
+            undistributed_policy: None,
+            admin: None,
+            unbonding_period: None,
+            delegate_stake: None,
+        };
+        instantiate(deps.as_mut(), env.clone(), info, msg).unwrap();
+        let info = mock_info("creator", &coins(1_000_000_000, "orai"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::FundRewards { stream_id: 0 }).unwrap();
+        let tiers = ExecuteMsg::SetLockTiers {
+            tiers: vec![LockTier {
+                duration: 100,
+                boost: Decimal::percent(200),
+            }],
+        };
+        execute(deps.as_mut(), env.clone(), mock_info("creator", &[]), tiers).unwrap();
+
+        let info = mock_info("alice", &coins(100, "orai"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::StakeLocked { tier: 0 }).unwrap();
+        let info = mock_info("bob", &coins(100, "orai"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Stake {}).unwrap();
+
+        // The lock runs on to the end of the day, 77781 seconds away
+        let unlock_at = (env.block.time.seconds() / 86_400 + 1) * 86_400;
+        assert_eq!(unlock_at - env.block.time.seconds(), 77_781);
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::User { address: "alice".to_string() }).unwrap();
+        let user: UserResponse = from_json(&res).unwrap();
+        assert_eq!(user.locks[0].unlock_at, unlock_at);
+        let expiries = LOCK_EXPIRIES
+            .keys(&deps.storage, None, None, Order::Ascending)
+            .collect::<StdResult<Vec<_>>>()
+            .unwrap();
+        assert_eq!(expiries, vec![unlock_at]);
+
+        // Nobody is settled until long after the lock expired, the boost
+        // still only counts until then
+        env.block.time = env.block.time.plus_seconds(77_781 + 3_000);
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::Rewards { address: "alice".to_string() }).unwrap();
+        let rewards: RewardsResponse = from_json(&res).unwrap();
+        assert_eq!(rewards.rewards[0].amount, Uint128::new(51_854 + 1_500));
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::Rewards { address: "bob".to_string() }).unwrap();
+        let rewards: RewardsResponse = from_json(&res).unwrap();
+        assert_eq!(rewards.rewards[0].amount, Uint128::new(25_927 + 1_500));
+
+        execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), ExecuteMsg::Claim {}).unwrap();
+        let config = read_config(&deps.storage).unwrap();
+        assert_eq!(config.total_effective_stake, Uint128::new(200));
+        let expiry = LOCK_EXPIRIES.load(&deps.storage, unlock_at).unwrap();
+        assert!(expiry.exchange_rates.is_some());
+        let res = query(deps.as_ref(), env, QueryMsg::Rewards { address: "bob".to_string() }).unwrap();
+        let rewards: RewardsResponse = from_json(&res).unwrap();
+        assert_eq!(rewards.rewards[0].amount, Uint128::new(25_927 + 1_500));
+    }
+
+    #[test]
+    fn top_up_does_not_reset_the_age_of_older_stake() {
+        let mut deps = mock_dependencies();
+        let mut env = mock_env();
+        let info = mock_info("creator", &[]);
+        let msg = InstantiateMsg {
+            monthly_reward: None,
+            stake_asset: AssetInfo::native("orai"),
+            reward_asset: AssetInfo::native("orai"),
+            undistributed_policy: None,
+            admin: None,
+            unbonding_period: None,
+            delegate_stake: None,
+        };
+        instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+        let msg = ExecuteMsg::UpdateConfig(UpdateConfigMsg {
+            early_withdrawal_penalty: Some(EarlyWithdrawalPenalty {
+                rate: Decimal::percent(50),
+                period: 10 * 86_400,
+                treasury: Some(Addr::unchecked("treasury")),
+            }),
+            ..UpdateConfigMsg::default()
+        });
+        execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+        let info = mock_info("alice", &coins(1_000, "orai"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Stake {}).unwrap();
+
+        // A single unit staked a day before the period ends is withdrawn first
+        env.block.time = env.block.time.plus_seconds(9 * 86_400);
+        let info = mock_info("alice", &coins(1, "orai"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Stake {}).unwrap();
+        let simulate = QueryMsg::SimulateWithdraw {
+            address: "alice".to_string(),
+            amount: Uint128::new(1_001),
+        };
+        let res = query(deps.as_ref(), env.clone(), simulate.clone()).unwrap();
+        let simulated: SimulateWithdrawResponse = from_json(&res).unwrap();
+        assert_eq!(simulated.penalty, Uint128::new(50));
+
+        env.block.time = env.block.time.plus_seconds(86_400);
+        let res = query(deps.as_ref(), env, simulate).unwrap();
+        let simulated: SimulateWithdrawResponse = from_json(&res).unwrap();
+        assert_eq!(simulated.penalty, Uint128::zero());
+    }
+
+    #[test]
+    fn withdrawal_after_top_up_is_charged_to_the_new_stake() {
+        let mut deps = mock_dependencies();
+        let mut env = mock_env();
+        let info = mock_info("creator", &[]);
+        let msg = InstantiateMsg {
+            monthly_reward: None,
+            stake_asset: AssetInfo::native("orai"),
+            reward_asset: AssetInfo::native("orai"),
+            undistributed_policy: None,
+            admin: None,
+            unbonding_period: None,
+            delegate_stake: None,
+        };
+        instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+        let msg = ExecuteMsg::UpdateConfig(UpdateConfigMsg {
+            early_withdrawal_penalty: Some(EarlyWithdrawalPenalty {
+                rate: Decimal::percent(50),
+                period: 10 * 86_400,
+                treasury: Some(Addr::unchecked("treasury")),
+            }),
+            ..UpdateConfigMsg::default()
+        });
+        execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+        let info = mock_info("alice", &coins(1_000, "orai"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Stake {}).unwrap();
+
+        // Topping up an old stake and withdrawing as much right away pays the
+        // full penalty on the new stake
+        env.block.time = env.block.time.plus_seconds(20 * 86_400);
+        let info = mock_info("alice", &coins(1_000, "orai"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Stake {}).unwrap();
+        let msg = ExecuteMsg::Withdraw {
+            amount: Uint128::new(1_000),
+        };
+        let res = execute(deps.as_mut(), env.clone(), mock_info("alice", &[]), msg).unwrap();
+        assert!(res
+            .attributes
+            .iter()
+            .any(|attr| attr.key == "penalty" && attr.value == "500"));
+
+        // What is left is the old stake, free to leave
+        let simulate = QueryMsg::SimulateWithdraw {
+            address: "alice".to_string(),
+            amount: Uint128::new(1_000),
+        };
+        let res = query(deps.as_ref(), env, simulate).unwrap();
+        let simulated: SimulateWithdrawResponse = from_json(&res).unwrap();
+        assert_eq!(simulated.penalty, Uint128::zero());
+    }
+
+    #[test]
+    fn emergency_withdraw_returns_full_principal_through_unbonding() {
+        let mut deps = mock_dependencies();
+        let mut env = mock_env();
+        let info = mock_info("creator", &[]);
+        let msg = InstantiateMsg {
+            monthly_reward: Some(Uint128::new(2_592_000)),
+            stake_asset: AssetInfo::native("orai"),
+            reward_asset: AssetInfo::native("orai"),
+            undistributed_policy: None,
+            admin: None,
+            unbonding_period: Some(600),
+            delegate_stake: None,
+        };
+        instantiate(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+        let msg = ExecuteMsg::UpdateConfig(UpdateConfigMsg {
+            early_withdrawal_penalty: Some(EarlyWithdrawalPenalty {
+                rate: Decimal::percent(50),
+                period: 10 * 86_400,
+                treasury: None,
+            }),
+            ..UpdateConfigMsg::default()
+        });
+        execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+        let tiers = ExecuteMsg::SetLockTiers {
+            tiers: vec![LockTier {
+                duration: 30 * 86_400,
+                boost: Decimal::percent(200),
+            }],
+        };
+        execute(deps.as_mut(), env.clone(), info, tiers).unwrap();
+
+        let info = mock_info("alice", &coins(100, "orai"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::StakeLocked { tier: 0 }).unwrap();
+        let info = mock_info("alice", &coins(50, "orai"));
+        execute(deps.as_mut(), env.clone(), info, ExecuteMsg::Stake {}).unwrap();
+        let pause = ExecuteMsg::SetPauseFlags {
+            stake: None,
+            withdraw: Some(true),
+            claim: None,
+            accrual: None,
+        };
+        execute(deps.as_mut(), env.clone(), mock_info("creator", &[]), pause).unwrap();
+
+        // Locked and penalized stake alike come back in full
+        let info = mock_info("alice", &[]);
+        let res = execute(deps.as_mut(), env.clone(), info.clone(), ExecuteMsg::EmergencyWithdraw {})
+            .unwrap();
+        assert!(res.messages.is_empty());
+        let res = query(deps.as_ref(), env.clone(), QueryMsg::Unbonding { address: "alice".to_string() })
+            .unwrap();
+        let unbonding: UnbondingResponse = from_json(&res).unwrap();
+        assert_eq!(unbonding.entries[0].amount, Uint128::new(150));
+        let config = read_config(&deps.storage).unwrap();
+        assert_eq!(config.total_effective_stake, Uint128::zero());
+        let expiries = LOCK_EXPIRIES
+            .range(&deps.storage, None, None, Order::Ascending)
+            .collect::<StdResult<Vec<_>>>()
+            .unwrap();
+        assert!(expiries.iter().all(|(_, expiry)| expiry.boost.is_zero()));
+
+        // And are released once unbonded, withdrawals still paused
+        env.block.time = env.block.time.plus_seconds(600);
+        let res = execute(deps.as_mut(), env, info, ExecuteMsg::ReleaseUnbonded {}).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "alice".to_string(),
+                amount: coins(150, "orai"),
+            })
+        );
+    }
+}
@@ -1,4 +1,4 @@
-use cosmwasm_std::StdError;
+use cosmwasm_std::{StdError, Uint128};
 use thiserror::Error;
 
 #[derive(Error, Debug, PartialEq)]
@@ -6,6 +6,9 @@ pub enum ContractError {
     #[error("{0}")]
     Std(#[from] StdError),
 
+    #[error("Semver parsing error: {0}")]
+    SemVer(String),
+
     #[error("Unauthorized")]
     Unauthorized {},
 
@@ -20,6 +23,75 @@ pub enum ContractError {
 
     #[error("Zero Claim")]
     InvalidClaim {},
+
+    #[error("No funds sent")]
+    NoFunds {},
+
+    #[error("Sent more than one denomination")]
+    MultipleDenoms {},
+
+    #[error("Invalid denom: expected {expected}, got {got}")]
+    InvalidDenom { expected: String, got: String },
+
+    #[error("Invalid token: expected {expected}, got {got}")]
+    InvalidToken { expected: String, got: String },
+
+    #[error("No unbonded funds ready to release")]
+    NothingToRelease {},
+
+    #[error("Reward stream {stream_id} does not exist")]
+    UnknownRewardStream { stream_id: u64 },
+
+    #[error("Campaign must end after it starts and cannot start in the past")]
+    InvalidCampaign {},
+
+    #[error("Delegating stake needs a native stake asset and a non-zero unbonding period")]
+    InvalidDelegationConfig {},
+
+    #[error("No validator to delegate to")]
+    NoValidators {},
+
+    #[error("Unknown validator {validator}")]
+    UnknownValidator { validator: String },
+
+    #[error("Lock tier needs a non-zero duration and a boost of at least 1")]
+    InvalidLockTier {},
+
+    #[error("Lock tier {tier} does not exist")]
+    UnknownLockTier { tier: u64 },
+
+    #[error("Only {available} can be withdrawn, more unlocks at {unlock_at}")]
+    StakeLocked { available: Uint128, unlock_at: u64 },
+
+    #[error("{operation} is paused")]
+    Paused { operation: String },
+
+    #[error("Penalty rate must be at most 1 and its period non-zero")]
+    InvalidPenalty {},
+
+    #[error("No reward stream pays the stake asset to redistribute penalties in")]
+    NoPenaltyStream {},
+
+    #[error("No reward stream pays the stake asset")]
+    NoCompoundStream {},
+
+    #[error("No reward stream pays {denom} to take harvested rewards")]
+    NoHarvestStream { denom: String },
+
+    #[error("Cannot migrate from contract {previous_contract}")]
+    InvalidMigration { previous_contract: String },
+
+    #[error("Cannot migrate from newer version {previous_version}")]
+    CannotDowngrade { previous_version: String },
+
+    #[error("Migrating from a version without ownership needs an owner")]
+    MissingOwner {},
+}
+
+impl From<semver::Error> for ContractError {
+    fn from(err: semver::Error) -> Self {
+        Self::SemVer(err.to_string())
+    }
 }
 
 impl From<ContractError> for StdError {
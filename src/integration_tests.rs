@@ -0,0 +1,689 @@
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::testing::mock_env;
+    use cosmwasm_std::{coins, to_json_binary, Addr, Decimal, Empty, Uint128, Validator};
+    use cw20::{BalanceResponse, Cw20Coin, Cw20ExecuteMsg, Cw20QueryMsg};
+    use cw_multi_test::{App, AppBuilder, Contract, ContractWrapper, Executor, StakingInfo};
+
+    use crate::asset::AssetInfo;
+    use crate::helpers::StakingContract;
+    use crate::msg::{
+        ExecuteMsg, InstantiateMsg, QueryMsg, ReceiveMsg, RewardsResponse, UnbondingResponse,
+        ValidatorAllocation, ValidatorsResponse,
+    };
+    use crate::state::UnbondingEntry;
+    use crate::ContractError;
+
+    const ADMIN: &str = "admin";
+    const USER: &str = "user";
+    const DENOM: &str = "orai";
+    const VALIDATOR1: &str = "validator1";
+    const VALIDATOR2: &str = "validator2";
+
+    fn contract_staking() -> Box<dyn Contract<Empty>> {
+        let contract = ContractWrapper::new(
+            crate::contract::execute,
+            crate::contract::instantiate,
+            crate::contract::query,
+        )
+        .with_reply(crate::contract::reply);
+        Box::new(contract)
+    }
+
+    fn contract_cw20() -> Box<dyn Contract<Empty>> {
+        let contract = ContractWrapper::new(
+            cw20_base::contract::execute,
+            cw20_base::contract::instantiate,
+            cw20_base::contract::query,
+        );
+        Box::new(contract)
+    }
+
+    fn mock_app() -> App {
+        AppBuilder::new().build(|router, api, storage| {
+            router
+                .bank
+                .init_balance(storage, &Addr::unchecked(USER), coins(1_000, DENOM))
+                .unwrap();
+            router
+                .staking
+                .setup(
+                    storage,
+                    StakingInfo {
+                        bonded_denom: DENOM.to_string(),
+                        unbonding_time: 60,
+                        apr: Decimal::percent(10),
+                    },
+                )
+                .unwrap();
+            for address in [VALIDATOR1, VALIDATOR2] {
+                let validator = Validator {
+                    address: address.to_string(),
+                    commission: Decimal::zero(),
+                    max_commission: Decimal::one(),
+                    max_change_rate: Decimal::one(),
+                };
+                router
+                    .staking
+                    .add_validator(api, storage, &mock_env().block, validator)
+                    .unwrap();
+            }
+        })
+    }
+
+    fn proper_instantiate() -> (App, StakingContract) {
+        instantiate_with_unbonding(None)
+    }
+
+    fn instantiate_with_unbonding(unbonding_period: Option<u64>) -> (App, StakingContract) {
+        let mut app = mock_app();
+        let staking = instantiate_staking(
+            &mut app,
+            AssetInfo::native(DENOM),
+            AssetInfo::native(DENOM),
+            unbonding_period,
+        );
+        (app, staking)
+    }
+
+    fn instantiate_staking(
+        app: &mut App,
+        stake_asset: AssetInfo,
+        reward_asset: AssetInfo,
+        unbonding_period: Option<u64>,
+    ) -> StakingContract {
+        let code_id = app.store_code(contract_staking());
+
+        let msg = InstantiateMsg {
+            monthly_reward: Some(Uint128::new(1_000_000)),
+            stake_asset,
+            reward_asset,
+            undistributed_policy: None,
+            admin: None,
+            unbonding_period,
+            delegate_stake: None,
+        };
+        let contract_addr = app
+            .instantiate_contract(code_id, Addr::unchecked(ADMIN), &msg, &[], "staking", None)
+            .unwrap();
+
+        StakingContract(contract_addr)
+    }
+
+    fn instantiate_delegating(app: &mut App) -> StakingContract {
+        let code_id = app.store_code(contract_staking());
+
+        let msg = InstantiateMsg {
+            monthly_reward: Some(Uint128::new(1_000_000)),
+            stake_asset: AssetInfo::native(DENOM),
+            reward_asset: AssetInfo::native(DENOM),
+            undistributed_policy: None,
+            admin: None,
+            unbonding_period: Some(120),
+            delegate_stake: Some(true),
+        };
+        let contract_addr = app
+            .instantiate_contract(code_id, Addr::unchecked(ADMIN), &msg, &[], "staking", None)
+            .unwrap();
+
+        StakingContract(contract_addr)
+    }
+
+    fn instantiate_cw20(app: &mut App, symbol: &str) -> Addr {
+        let code_id = app.store_code(contract_cw20());
+
+        let msg = cw20_base::msg::InstantiateMsg {
+            name: format!("{} token", symbol),
+            symbol: symbol.to_string(),
+            decimals: 6,
+            initial_balances: vec![
+                Cw20Coin {
+                    address: USER.to_string(),
+                    amount: Uint128::new(1_000),
+                },
+                Cw20Coin {
+                    address: ADMIN.to_string(),
+                    amount: Uint128::new(1_000_000_000),
+                },
+            ],
+            mint: None,
+            marketing: None,
+        };
+        app.instantiate_contract(code_id, Addr::unchecked(ADMIN), &msg, &[], symbol, None)
+            .unwrap()
+    }
+
+    fn cw20_balance(app: &App, token: &Addr, addr: &Addr) -> Uint128 {
+        let res: BalanceResponse = app
+            .wrap()
+            .query_wasm_smart(
+                token,
+                &Cw20QueryMsg::Balance {
+                    address: addr.to_string(),
+                },
+            )
+            .unwrap();
+        res.balance
+    }
+
+    fn cw20_stake(staking: &StakingContract, amount: u128) -> Cw20ExecuteMsg {
+        Cw20ExecuteMsg::Send {
+            contract: staking.addr().to_string(),
+            amount: Uint128::new(amount),
+            msg: to_json_binary(&ReceiveMsg::Stake {}).unwrap(),
+        }
+    }
+
+    fn cw20_fund(staking: &StakingContract, stream_id: u64, amount: u128) -> Cw20ExecuteMsg {
+        Cw20ExecuteMsg::Send {
+            contract: staking.addr().to_string(),
+            amount: Uint128::new(amount),
+            msg: to_json_binary(&ReceiveMsg::FundRewards { stream_id }).unwrap(),
+        }
+    }
+
+    fn validators(app: &App, staking: &StakingContract) -> Vec<ValidatorAllocation> {
+        let res: ValidatorsResponse = app
+            .wrap()
+            .query_wasm_smart(staking.addr(), &QueryMsg::Validators {})
+            .unwrap();
+        res.validators
+    }
+
+    fn delegated(app: &App, staking: &StakingContract, validator: &str) -> Uint128 {
+        app.wrap()
+            .query_delegation(staking.addr(), validator)
+            .unwrap()
+            .map_or(Uint128::zero(), |delegation| delegation.amount.amount)
+    }
+
+    fn balance(app: &App, addr: &Addr) -> Uint128 {
+        app.wrap().query_balance(addr, DENOM).unwrap().amount
+    }
+
+    #[test]
+    fn withdraw_returns_staked_funds() {
+        let (mut app, staking) = proper_instantiate();
+        let user = Addr::unchecked(USER);
+
+        app.execute_contract(
+            user.clone(),
+            staking.addr(),
+            &ExecuteMsg::Stake {},
+            &coins(100, DENOM),
+        )
+        .unwrap();
+        assert_eq!(balance(&app, &user), Uint128::new(900));
+        assert_eq!(balance(&app, &staking.addr()), Uint128::new(100));
+
+        app.update_block(|block| block.time = block.time.plus_seconds(86_400));
+
+        let msg = ExecuteMsg::Withdraw {
+            amount: Uint128::new(40),
+        };
+        app.execute_contract(user.clone(), staking.addr(), &msg, &[])
+            .unwrap();
+        assert_eq!(balance(&app, &user), Uint128::new(940));
+        assert_eq!(balance(&app, &staking.addr()), Uint128::new(60));
+    }
+
+    #[test]
+    fn withdraw_rejects_more_than_staked() {
+        let (mut app, staking) = proper_instantiate();
+        let user = Addr::unchecked(USER);
+
+        app.execute_contract(
+            user.clone(),
+            staking.addr(),
+            &ExecuteMsg::Stake {},
+            &coins(100, DENOM),
+        )
+        .unwrap();
+
+        let msg = ExecuteMsg::Withdraw {
+            amount: Uint128::new(101),
+        };
+        let err = app
+            .execute_contract(user.clone(), staking.addr(), &msg, &[])
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::InsufficientStaked {}
+        );
+        assert_eq!(balance(&app, &user), Uint128::new(900));
+        assert_eq!(balance(&app, &staking.addr()), Uint128::new(100));
+    }
+
+    #[test]
+    fn withdraw_waits_out_unbonding_period() {
+        let (mut app, staking) = instantiate_with_unbonding(Some(86_400));
+        let user = Addr::unchecked(USER);
+
+        app.execute_contract(
+            user.clone(),
+            staking.addr(),
+            &ExecuteMsg::Stake {},
+            &coins(100, DENOM),
+        )
+        .unwrap();
+
+        let msg = ExecuteMsg::Withdraw {
+            amount: Uint128::new(40),
+        };
+        app.execute_contract(user.clone(), staking.addr(), &msg, &[])
+            .unwrap();
+        assert_eq!(balance(&app, &user), Uint128::new(900));
+
+        let release_at = app.block_info().time.seconds() + 86_400;
+        let unbonding: UnbondingResponse = app
+            .wrap()
+            .query_wasm_smart(
+                staking.addr(),
+                &QueryMsg::Unbonding {
+                    address: USER.to_string(),
+                },
+            )
+            .unwrap();
+        assert_eq!(
+            unbonding.entries,
+            vec![UnbondingEntry {
+                amount: Uint128::new(40),
+                release_at,
+            }]
+        );
+
+        app.update_block(|block| block.time = block.time.plus_seconds(86_399));
+        let err = app
+            .execute_contract(
+                user.clone(),
+                staking.addr(),
+                &ExecuteMsg::ReleaseUnbonded {},
+                &[],
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::NothingToRelease {}
+        );
+
+        app.update_block(|block| block.time = block.time.plus_seconds(1));
+        app.execute_contract(
+            user.clone(),
+            staking.addr(),
+            &ExecuteMsg::ReleaseUnbonded {},
+            &[],
+        )
+        .unwrap();
+        assert_eq!(balance(&app, &user), Uint128::new(940));
+        assert_eq!(balance(&app, &staking.addr()), Uint128::new(60));
+
+        let unbonding: UnbondingResponse = app
+            .wrap()
+            .query_wasm_smart(
+                staking.addr(),
+                &QueryMsg::Unbonding {
+                    address: USER.to_string(),
+                },
+            )
+            .unwrap();
+        assert!(unbonding.entries.is_empty());
+    }
+
+    #[test]
+    fn cw20_stake_and_withdraw() {
+        let mut app = mock_app();
+        let token = instantiate_cw20(&mut app, "TKN");
+        let stake_asset = AssetInfo::Cw20 {
+            contract_addr: token.clone(),
+        };
+        let staking = instantiate_staking(&mut app, stake_asset, AssetInfo::native(DENOM), None);
+        let user = Addr::unchecked(USER);
+
+        app.execute_contract(user.clone(), token.clone(), &cw20_stake(&staking, 100), &[])
+            .unwrap();
+        assert_eq!(cw20_balance(&app, &token, &user), Uint128::new(900));
+        assert_eq!(
+            cw20_balance(&app, &token, &staking.addr()),
+            Uint128::new(100)
+        );
+
+        let msg = ExecuteMsg::Withdraw {
+            amount: Uint128::new(40),
+        };
+        app.execute_contract(user.clone(), staking.addr(), &msg, &[])
+            .unwrap();
+        assert_eq!(cw20_balance(&app, &token, &user), Uint128::new(940));
+        assert_eq!(
+            cw20_balance(&app, &token, &staking.addr()),
+            Uint128::new(60)
+        );
+    }
+
+    #[test]
+    fn cw20_pool_rejects_other_tokens() {
+        let mut app = mock_app();
+        let token = instantiate_cw20(&mut app, "TKN");
+        let other = instantiate_cw20(&mut app, "OTHER");
+        let stake_asset = AssetInfo::Cw20 {
+            contract_addr: token.clone(),
+        };
+        let staking = instantiate_staking(&mut app, stake_asset, AssetInfo::native(DENOM), None);
+        let user = Addr::unchecked(USER);
+
+        let err = app
+            .execute_contract(user.clone(), other.clone(), &cw20_stake(&staking, 100), &[])
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::InvalidToken {
+                expected: token.to_string(),
+                got: other.to_string(),
+            }
+        );
+
+        let err = app
+            .execute_contract(
+                user.clone(),
+                staking.addr(),
+                &ExecuteMsg::Stake {},
+                &coins(100, DENOM),
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::InvalidToken {
+                expected: token.to_string(),
+                got: "native funds".to_string(),
+            }
+        );
+        assert_eq!(cw20_balance(&app, &other, &user), Uint128::new(1_000));
+        assert_eq!(balance(&app, &user), Uint128::new(1_000));
+    }
+
+    #[test]
+    fn rewards_paid_in_reward_asset() {
+        let mut app = mock_app();
+        let token = instantiate_cw20(&mut app, "RWD");
+        let reward_asset = AssetInfo::Cw20 {
+            contract_addr: token.clone(),
+        };
+        let staking = instantiate_staking(&mut app, AssetInfo::native(DENOM), reward_asset, None);
+        let user = Addr::unchecked(USER);
+
+        app.execute_contract(
+            Addr::unchecked(ADMIN),
+            token.clone(),
+            &cw20_fund(&staking, 0, 1_000_000),
+            &[],
+        )
+        .unwrap();
+
+        app.execute_contract(
+            user.clone(),
+            staking.addr(),
+            &ExecuteMsg::Stake {},
+            &coins(100, DENOM),
+        )
+        .unwrap();
+
+        // A sole staker earns the whole 1_000_000 per 30 days
+        app.update_block(|block| block.time = block.time.plus_seconds(86_400));
+
+        app.execute_contract(user.clone(), staking.addr(), &ExecuteMsg::Claim {}, &[])
+            .unwrap();
+        assert_eq!(
+            cw20_balance(&app, &token, &user),
+            Uint128::new(1_000 + 33_333)
+        );
+        assert_eq!(balance(&app, &user), Uint128::new(900));
+
+        let msg = ExecuteMsg::Withdraw {
+            amount: Uint128::new(100),
+        };
+        app.execute_contract(user.clone(), staking.addr(), &msg, &[])
+            .unwrap();
+        assert_eq!(balance(&app, &user), Uint128::new(1_000));
+        assert_eq!(
+            cw20_balance(&app, &token, &user),
+            Uint128::new(1_000 + 33_333)
+        );
+    }
+
+    #[test]
+    fn claim_pays_every_reward_stream() {
+        let mut app = mock_app();
+        let token = instantiate_cw20(&mut app, "RWD");
+        let partner = instantiate_cw20(&mut app, "PTN");
+        let reward_asset = AssetInfo::Cw20 {
+            contract_addr: token.clone(),
+        };
+        let staking = instantiate_staking(&mut app, AssetInfo::native(DENOM), reward_asset, None);
+        let user = Addr::unchecked(USER);
+
+        let msg = ExecuteMsg::AddRewardStream {
+            asset: AssetInfo::Cw20 {
+                contract_addr: partner.clone(),
+            },
+            monthly_reward: Uint128::new(1_000_000),
+        };
+        app.execute_contract(Addr::unchecked(ADMIN), staking.addr(), &msg, &[])
+            .unwrap();
+        for (stream_id, token) in [&token, &partner].into_iter().enumerate() {
+            app.execute_contract(
+                Addr::unchecked(ADMIN),
+                token.clone(),
+                &cw20_fund(&staking, stream_id as u64, 1_000_000),
+                &[],
+            )
+            .unwrap();
+        }
+
+        app.execute_contract(
+            user.clone(),
+            staking.addr(),
+            &ExecuteMsg::Stake {},
+            &coins(100, DENOM),
+        )
+        .unwrap();
+        app.update_block(|block| block.time = block.time.plus_seconds(86_400));
+
+        app.execute_contract(user.clone(), staking.addr(), &ExecuteMsg::Claim {}, &[])
+            .unwrap();
+        assert_eq!(
+            cw20_balance(&app, &token, &user),
+            Uint128::new(1_000 + 33_333)
+        );
+        assert_eq!(
+            cw20_balance(&app, &partner, &user),
+            Uint128::new(1_000 + 33_333)
+        );
+    }
+
+    #[test]
+    fn stake_delegated_across_validator_set() {
+        let mut app = mock_app();
+        let staking = instantiate_delegating(&mut app);
+        let user = Addr::unchecked(USER);
+
+        let msg = ExecuteMsg::UpdateValidators {
+            add: vec!["nobody".to_string()],
+            remove: vec![],
+        };
+        let err = app
+            .execute_contract(Addr::unchecked(ADMIN), staking.addr(), &msg, &[])
+            .unwrap_err();
+        assert_eq!(
+            err.downcast::<ContractError>().unwrap(),
+            ContractError::UnknownValidator {
+                validator: "nobody".to_string(),
+            }
+        );
+        let msg = ExecuteMsg::UpdateValidators {
+            add: vec![VALIDATOR1.to_string(), VALIDATOR2.to_string()],
+            remove: vec![],
+        };
+        app.execute_contract(Addr::unchecked(ADMIN), staking.addr(), &msg, &[])
+            .unwrap();
+
+        // Each stake goes to the validator with the least delegated
+        for amount in [100, 50] {
+            app.execute_contract(
+                user.clone(),
+                staking.addr(),
+                &ExecuteMsg::Stake {},
+                &coins(amount, DENOM),
+            )
+            .unwrap();
+        }
+        assert_eq!(delegated(&app, &staking, VALIDATOR1), Uint128::new(100));
+        assert_eq!(delegated(&app, &staking, VALIDATOR2), Uint128::new(50));
+        assert_eq!(balance(&app, &staking.addr()), Uint128::zero());
+
+        // Withdrawals undelegate from the largest allocation first
+        let msg = ExecuteMsg::Withdraw {
+            amount: Uint128::new(120),
+        };
+        app.execute_contract(user.clone(), staking.addr(), &msg, &[])
+            .unwrap();
+        assert_eq!(
+            validators(&app, &staking),
+            vec![
+                ValidatorAllocation {
+                    address: VALIDATOR1.to_string(),
+                    delegated: Uint128::zero(),
+                },
+                ValidatorAllocation {
+                    address: VALIDATOR2.to_string(),
+                    delegated: Uint128::new(30),
+                },
+            ]
+        );
+        assert_eq!(delegated(&app, &staking, VALIDATOR2), Uint128::new(30));
+
+        app.update_block(|block| block.time = block.time.plus_seconds(120));
+        // Matured undelegations are paid out at the end of the block
+        app.update_block(|block| block.height += 1);
+        app.execute_contract(
+            user.clone(),
+            staking.addr(),
+            &ExecuteMsg::ReleaseUnbonded {},
+            &[],
+        )
+        .unwrap();
+        assert_eq!(balance(&app, &user), Uint128::new(970));
+
+        // Removing a validator moves its stake onto the rest of the set
+        let msg = ExecuteMsg::UpdateValidators {
+            add: vec![],
+            remove: vec![VALIDATOR2.to_string()],
+        };
+        app.execute_contract(Addr::unchecked(ADMIN), staking.addr(), &msg, &[])
+            .unwrap();
+        assert_eq!(
+            validators(&app, &staking),
+            vec![ValidatorAllocation {
+                address: VALIDATOR1.to_string(),
+                delegated: Uint128::new(30),
+            }]
+        );
+        assert_eq!(delegated(&app, &staking, VALIDATOR1), Uint128::new(30));
+        assert_eq!(delegated(&app, &staking, VALIDATOR2), Uint128::zero());
+    }
+
+    #[test]
+    fn harvest_shares_staking_rewards() {
+        let mut app = mock_app();
+        let staking = instantiate_delegating(&mut app);
+        let user = Addr::unchecked(USER);
+
+        let msg = ExecuteMsg::UpdateValidators {
+            add: vec![VALIDATOR1.to_string(), VALIDATOR2.to_string()],
+            remove: vec![],
+        };
+        app.execute_contract(Addr::unchecked(ADMIN), staking.addr(), &msg, &[])
+            .unwrap();
+        app.execute_contract(
+            user.clone(),
+            staking.addr(),
+            &ExecuteMsg::Stake {},
+            &coins(1_000, DENOM),
+        )
+        .unwrap();
+
+        // 10% a year on the delegated 1000, with the reward reserve left empty
+        app.update_block(|block| block.time = block.time.plus_seconds(365 * 86_400));
+        app.execute_contract(
+            Addr::unchecked(ADMIN),
+            staking.addr(),
+            &ExecuteMsg::Harvest {},
+            &[],
+        )
+        .unwrap();
+        assert_eq!(balance(&app, &staking.addr()), Uint128::new(100));
+
+        let rewards: RewardsResponse = app
+            .wrap()
+            .query_wasm_smart(
+                staking.addr(),
+                &QueryMsg::Rewards {
+                    address: USER.to_string(),
+                },
+            )
+            .unwrap();
+        assert_eq!(rewards.rewards[0].amount, Uint128::new(100));
+
+        app.execute_contract(user.clone(), staking.addr(), &ExecuteMsg::Claim {}, &[])
+            .unwrap();
+        assert_eq!(balance(&app, &user), Uint128::new(100));
+        assert_eq!(balance(&app, &staking.addr()), Uint128::zero());
+    }
+
+    #[test]
+    fn delegation_change_shares_pending_staking_rewards() {
+        let mut app = mock_app();
+        let staking = instantiate_delegating(&mut app);
+        let user = Addr::unchecked(USER);
+
+        let msg = ExecuteMsg::UpdateValidators {
+            add: vec![VALIDATOR1.to_string()],
+            remove: vec![],
+        };
+        app.execute_contract(Addr::unchecked(ADMIN), staking.addr(), &msg, &[])
+            .unwrap();
+        app.execute_contract(
+            user.clone(),
+            staking.addr(),
+            &ExecuteMsg::Stake {},
+            &coins(1_000, DENOM),
+        )
+        .unwrap();
+
+        // Undelegating pays out the year's 10%, the principal is still unbonding
+        app.update_block(|block| block.time = block.time.plus_seconds(365 * 86_400));
+        app.execute_contract(
+            user.clone(),
+            staking.addr(),
+            &ExecuteMsg::Withdraw {
+                amount: Uint128::new(500),
+            },
+            &[],
+        )
+        .unwrap();
+        assert_eq!(balance(&app, &staking.addr()), Uint128::new(100));
+
+        let rewards: RewardsResponse = app
+            .wrap()
+            .query_wasm_smart(
+                staking.addr(),
+                &QueryMsg::Rewards {
+                    address: USER.to_string(),
+                },
+            )
+            .unwrap();
+        assert_eq!(rewards.rewards[0].amount, Uint128::new(100));
+
+        app.execute_contract(user.clone(), staking.addr(), &ExecuteMsg::Claim {}, &[])
+            .unwrap();
+        assert_eq!(balance(&app, &staking.addr()), Uint128::zero());
+    }
+}
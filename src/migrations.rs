@@ -0,0 +1,126 @@
+use cosmwasm_std::{Addr, DepsMut, Env};
+use semver::Version;
+
+use crate::ContractError;
+
+/// Brings state written by `previous` up to the current layout, one version
+/// step at a time. `None` is a deployment from before cw2 version tracking.
+pub fn migrate_state(
+    deps: DepsMut,
+    env: &Env,
+    previous: Option<&Version>,
+    owner: Option<Addr>,
+) -> Result<(), ContractError> {
+    if previous.is_none() {
+        let owner = owner.ok_or(ContractError::MissingOwner {})?;
+        v0_1::migrate(deps.storage, env, owner)?;
+    }
+    Ok(())
+}
+
+/// Layout of the contract before version tracking: `Coin` amounts, `Uint128`
+/// exchange rates scaled by 1e6 and no ownership.
+pub mod v0_1 {
+    use cosmwasm_std::{Addr, Coin, Decimal256, Env, Order, StdResult, Storage, Uint128};
+    use cw_storage_plus::{Item, Map};
+    use serde::{Deserialize, Serialize};
+
+    use crate::asset::AssetInfo;
+    use crate::state::{
+        self, EarlyWithdrawalPenalty, Ownership, PauseFlags, RewardStream, UndistributedPolicy,
+        UserReward, OWNERSHIP,
+    };
+
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+    pub struct Config {
+        pub monthly_reward: Coin,
+        pub total_value_locked: Coin,
+        pub eps: Uint128,
+        pub last_update_time: u64,
+        pub global_exchange_rate: Uint128,
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+    pub struct User {
+        pub staked_amount: Coin,
+        pub exchange_rate: Uint128,
+        pub last_staked_time: u64,
+        pub rewards: Uint128,
+    }
+
+    pub const CONFIG: Item<Config> = Item::new("config");
+    pub const USERS: Map<&Addr, User> = Map::new("users");
+
+    /// Rewrites `Config` and every `User` in the current layout. `Coin`s
+    /// become plain amounts of the native `stake_asset` and the single reward
+    /// stream's asset.
+    ///
+    /// The legacy index divided the emission by the TVL twice, so it does not
+    /// carry over to the current per-token index. Rewards pending on it are
+    /// computed with the legacy formula and banked along with `User.rewards`.
+    /// Accrual restarts from the migration time with a zero index, once the
+    /// owner funds the stream's reserve.
+    pub fn migrate(storage: &mut dyn Storage, env: &Env, owner: Addr) -> StdResult<()> {
+        let legacy_config = CONFIG.load(storage)?;
+        let config = state::Config {
+            stake_asset: AssetInfo::native(legacy_config.total_value_locked.denom.clone()),
+            total_value_locked: legacy_config.total_value_locked.amount,
+            total_effective_stake: legacy_config.total_value_locked.amount,
+            last_update_time: env.block.time.seconds(),
+            reward_streams: vec![RewardStream::new(
+                AssetInfo::native(legacy_config.monthly_reward.denom.clone()),
+                legacy_config.monthly_reward.amount,
+            )],
+            undistributed_policy: UndistributedPolicy::Rollover,
+            unbonding_period: 0,
+            delegate_stake: false,
+            lock_tiers: vec![],
+            passed_lock_expiries: vec![],
+            early_withdrawal_penalty: EarlyWithdrawalPenalty::default(),
+            pause_flags: PauseFlags::default(),
+            guardian: None,
+        };
+        state::save_config(storage, &config)?;
+
+        let users = USERS
+            .range(storage, None, None, Order::Ascending)
+            .collect::<StdResult<Vec<_>>>()?;
+        for (addr, legacy) in users {
+            let user = state::User {
+                staked_amount: legacy.staked_amount.amount,
+                effective_stake: legacy.staked_amount.amount,
+                locks: vec![],
+                last_staked_time: legacy.last_staked_time,
+                deposits: vec![],
+                rewards: vec![UserReward {
+                    exchange_rate: Decimal256::zero(),
+                    rewards: legacy.rewards + pending_rewards(&legacy_config, &legacy),
+                }],
+                auto_compound: false,
+            };
+            state::USERS.save(storage, &addr, &user)?;
+        }
+
+        OWNERSHIP.save(
+            storage,
+            &Ownership {
+                owner: Some(owner),
+                pending_owner: None,
+            },
+        )
+    }
+
+    /// Rewards `user` accrued on the legacy index since their last
+    /// settlement, as the legacy contract computed them.
+    fn pending_rewards(config: &Config, user: &User) -> Uint128 {
+        if config.total_value_locked.amount.is_zero() {
+            return Uint128::zero();
+        }
+        let exchange_rate_diff = config
+            .global_exchange_rate
+            .saturating_sub(user.exchange_rate);
+        user.staked_amount
+            .amount
+            .multiply_ratio(exchange_rate_diff, Uint128::new(1_000_000))
+    }
+}
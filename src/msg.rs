@@ -1,20 +1,157 @@
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use cosmwasm_std::{Coin, Uint128};
+use cosmwasm_std::{Addr, Decimal, Uint128};
+use cw20::Cw20ReceiveMsg;
+
+use crate::asset::AssetInfo;
+use crate::state::{
+    EarlyWithdrawalPenalty, Lock, LockTier, PauseFlags, RewardStream, UnbondingEntry,
+    UndistributedPolicy,
+};
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub struct InstantiateMsg {
-    pub monthly_reward: Coin,
-    pub eps: Uint128,
+    /// Amount of `reward_asset` emitted every 30 days on top of campaigns.
+    /// Defaults to none, leaving emission to `ScheduleCampaign`.
+    pub monthly_reward: Option<Uint128>,
+    /// Token accepted for staking and returned on withdrawal.
+    pub stake_asset: AssetInfo,
+    /// Token paid by the first reward stream, more can be added later with
+    /// `AddRewardStream`.
+    pub reward_asset: AssetInfo,
+    /// Defaults to `Rollover`.
+    pub undistributed_policy: Option<UndistributedPolicy>,
+    /// Contract owner, defaults to the instantiating address.
+    pub admin: Option<String>,
+    /// Seconds withdrawn stake stays locked before it can be released,
+    /// defaults to 0 for instant withdrawals.
+    pub unbonding_period: Option<u64>,
+    /// Delegates staked funds to a validator set managed by the owner,
+    /// defaults to false. Needs a native `stake_asset` also paid as
+    /// `reward_asset`, and an `unbonding_period` at least as long as the
+    /// chain's.
+    pub delegate_stake: Option<bool>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum ExecuteMsg {
-    Stake { amount: Coin },
-    Withdraw { amount: Coin },
+    /// Stakes the native funds sent along.
+    Stake {},
+    /// Stakes the native funds sent along, locked for the duration of lock
+    /// tier `tier` in return for its boost.
+    StakeLocked { tier: u64 },
+    /// Stakes CW20 tokens sent with `Cw20ExecuteMsg::Send`.
+    Receive(Cw20ReceiveMsg),
+    Withdraw { amount: Uint128 },
+    /// Withdraws all of the sender's stake, locked or not and without any
+    /// early withdrawal penalty, forfeiting every reward. Allowed while
+    /// paused.
+    EmergencyWithdraw {},
+    /// Pays the sender's rewards on every stream.
     Claim {},
+    /// Stakes the sender's rewards paid in the stake asset.
+    Compound {},
+    /// Compounds the sender's rewards paid in the stake asset whenever their
+    /// position is settled, starting with those accrued so far.
+    SetAutoCompound { enabled: bool },
+    /// Adds the native funds sent along to the reserve of a stream whose
+    /// asset they are. Anyone can fund a stream.
+    FundRewards { stream_id: u64 },
+    /// Sends the owner any rewards emitted while nothing was staked.
+    ReclaimUndistributed {},
+    UpdateOwnership(OwnershipAction),
+    /// Changes the pool settings, settling everything accrued under the old
+    /// ones first. Owner only.
+    UpdateConfig(UpdateConfigMsg),
+    /// Pauses or resumes operations, `None` leaves a flag as it is. Owner or
+    /// guardian, the guardian can only pause.
+    SetPauseFlags {
+        stake: Option<bool>,
+        withdraw: Option<bool>,
+        claim: Option<bool>,
+        accrual: Option<bool>,
+    },
+    /// Sets or clears the guardian. Owner only.
+    SetGuardian { guardian: Option<String> },
+    /// Replaces the lock tiers offered to new stakes, existing locks keep
+    /// their terms. Owner only.
+    SetLockTiers { tiers: Vec<LockTier> },
+    /// Starts emitting `monthly_reward` of `asset` to stakers as a new
+    /// stream, 0 to leave emission to campaigns. Owner only.
+    AddRewardStream {
+        asset: AssetInfo,
+        monthly_reward: Uint128,
+    },
+    /// Changes the emission rate of a stream, settling everything accrued at
+    /// the old rate first. Owner only.
+    UpdateRewardStream {
+        stream_id: u64,
+        monthly_reward: Uint128,
+    },
+    /// Queues `budget` of a stream's asset to be emitted evenly between
+    /// `start` and `end`. The stream's reserve still needs funding to pay
+    /// for it. Owner only.
+    ScheduleCampaign {
+        stream_id: u64,
+        start: u64,
+        end: u64,
+        budget: Uint128,
+    },
+    /// Changes the validators stake is delegated to. Stake on a removed
+    /// validator is redelegated to the rest of the set. Owner only.
+    UpdateValidators {
+        add: Vec<String>,
+        remove: Vec<String>,
+    },
+    /// Withdraws the chain's staking rewards from every validator and shares
+    /// them between stakers through the first reward stream paid in the
+    /// bonded denom. Anyone can harvest.
+    Harvest {},
+    /// Pays out the sender's unbonding withdrawals whose release time has
+    /// passed.
+    ReleaseUnbonded {},
+}
+
+/// Message embedded in a `Cw20ReceiveMsg`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ReceiveMsg {
+    Stake {},
+    StakeLocked { tier: u64 },
+    /// Adds the tokens to the reserve of a stream paid in them.
+    FundRewards { stream_id: u64 },
+}
+
+/// Settings to change, `None` leaves the current value.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct UpdateConfigMsg {
+    /// New rate of stream 0, same as `UpdateRewardStream` for it.
+    pub monthly_reward: Option<Uint128>,
+    pub undistributed_policy: Option<UndistributedPolicy>,
+    pub unbonding_period: Option<u64>,
+    pub early_withdrawal_penalty: Option<EarlyWithdrawalPenalty>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum OwnershipAction {
+    /// Proposes `new_owner`, replacing any earlier proposal.
+    TransferOwnership { new_owner: String },
+    /// Completes a transfer, sent by the proposed owner.
+    AcceptOwnership,
+    /// Leaves the contract without an owner for good.
+    RenounceOwnership,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct MigrateMsg {
+    /// Owner to set when migrating from a version without ownership, where
+    /// it is required. Ignored otherwise.
+    pub owner: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -22,19 +159,180 @@ pub enum ExecuteMsg {
 pub enum QueryMsg {
     Configure {},
     User { address: String },
+    Undistributed {},
+    Ownership {},
+    Unbonding { address: String },
+    /// Pending rewards of `address` on every stream. With auto-compounding
+    /// on, those in the stake asset are staked once the user is next settled.
+    Rewards { address: String },
+    /// Funding and emission totals of every stream.
+    RewardReserve {},
+    /// Campaigns of every stream, by whether they have ended, are running or
+    /// are yet to start. Ended campaigns are paged through in the order they
+    /// were archived, numbered from 0: `start_after` is the number of the last
+    /// one received.
+    Campaigns {
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    /// Validator set and the stake delegated to each.
+    Validators {},
+    /// Penalty `address` would pay to withdraw `amount` now. Does not check
+    /// the amount is staked and unlocked.
+    SimulateWithdraw { address: String, amount: Uint128 },
+    /// Operations currently paused and who besides the owner can pause them.
+    PauseFlags {},
+    /// Rewards `address` would have pending at `at_time`, had they staked
+    /// `extra_stake` more now. Projects the current emission schedule and
+    /// assumes nobody else's stake changes, and nothing being compounded.
+    SimulateRewards {
+        address: String,
+        at_time: u64,
+        extra_stake: Option<Uint128>,
+    },
+    /// Current yield of every stream per unlocked staked token.
+    Apr {},
+    /// Every staker's position, ordered by address.
+    AllStakers {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct UserResponse {
-    pub staked_amount: Coin,
-    pub exchange_rate: Uint128,
-    pub rewards: Uint128,
+    pub staked_amount: Uint128,
+    pub effective_stake: Uint128,
+    pub locks: Vec<Lock>,
+    pub rewards: Vec<RewardAmount>,
+    pub auto_compound: bool,
+}
+
+/// Amount owed on one reward stream.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RewardAmount {
+    pub stream_id: u64,
+    pub asset: AssetInfo,
+    pub amount: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RewardsResponse {
+    pub rewards: Vec<RewardAmount>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct ConfigResponse {
-    pub monthly_reward: Coin,
-    pub total_value_locked: Coin,
-    pub eps: Uint128,
-    pub global_exchange_rate: Uint128,
+    pub stake_asset: AssetInfo,
+    pub total_value_locked: Uint128,
+    pub total_effective_stake: Uint128,
+    pub reward_streams: Vec<RewardStream>,
+    pub lock_tiers: Vec<LockTier>,
+    pub early_withdrawal_penalty: EarlyWithdrawalPenalty,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct UndistributedResponse {
+    pub policy: UndistributedPolicy,
+    pub rewards: Vec<RewardAmount>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RewardReserveResponse {
+    pub streams: Vec<StreamReserve>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct StreamReserve {
+    pub stream_id: u64,
+    pub asset: AssetInfo,
+    pub funded: Uint128,
+    /// Emitted so far, rounded down.
+    pub distributed: Uint128,
+    pub claimed: Uint128,
+    /// Seconds until the remaining reserve runs out at the current rate,
+    /// including running campaigns, `None` while the stream emits nothing.
+    pub runway: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CampaignsResponse {
+    /// Ended campaigns, one page of them. The last page also holds those
+    /// ended since the index was last updated.
+    pub past: Vec<CampaignInfo>,
+    pub active: Vec<CampaignInfo>,
+    /// Sorted by start time.
+    pub upcoming: Vec<CampaignInfo>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CampaignInfo {
+    pub stream_id: u64,
+    pub asset: AssetInfo,
+    pub start: u64,
+    pub end: u64,
+    pub budget: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SimulateWithdrawResponse {
+    pub penalty: Uint128,
+    /// What is left of the withdrawal for the staker.
+    pub payout: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AprResponse {
+    pub streams: Vec<StreamApr>,
+}
+
+/// Yield of a stream in its own asset per unlocked staked token, so only a
+/// rate in the usual sense when it pays the stake asset. A locked token earns
+/// these rates multiplied by its lock's boost. All `None` while nothing is
+/// staked.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct StreamApr {
+    pub stream_id: u64,
+    pub asset: AssetInfo,
+    /// Reward emitted per staked token every second right now.
+    pub per_second: Option<Decimal>,
+    /// Reward over the coming year, no more than the stream's reserve.
+    pub apr: Option<Decimal>,
+    /// `apr` with rewards compounded daily.
+    pub apy: Option<Decimal>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AllStakersResponse {
+    pub stakers: Vec<StakerInfo>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct StakerInfo {
+    pub address: Addr,
+    pub staked_amount: Uint128,
+    /// Pending rewards on every stream.
+    pub rewards: Vec<RewardAmount>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PauseFlagsResponse {
+    pub flags: PauseFlags,
+    pub guardian: Option<Addr>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ValidatorsResponse {
+    pub validators: Vec<ValidatorAllocation>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ValidatorAllocation {
+    pub address: String,
+    pub delegated: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct UnbondingResponse {
+    pub entries: Vec<UnbondingEntry>,
 }
@@ -1,30 +1,269 @@
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use cosmwasm_std::{Coin, StdResult, Storage, Uint128, Addr};
+use cosmwasm_std::{Addr, Decimal, Decimal256, Order, StdResult, Storage, Uint128};
 use cw_storage_plus::{Item, Map};
 
+use crate::asset::AssetInfo;
+use crate::contract::calculate_eps;
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct Config {
-    pub monthly_reward: Coin,
-    pub total_value_locked: Coin,
-    pub eps: Uint128,
+    /// Token accepted for staking.
+    pub stake_asset: AssetInfo,
+    pub total_value_locked: Uint128,
+    /// Sum of every user's `effective_stake`, the stake rewards are shared
+    /// over.
+    pub total_effective_stake: Uint128,
     pub last_update_time: u64,
-    pub global_exchange_rate: Uint128,
+    /// Tokens emitted to stakers, a stream's id is its position here.
+    pub reward_streams: Vec<RewardStream>,
+    pub undistributed_policy: UndistributedPolicy,
+    /// Seconds between a withdrawal and its release, 0 pays out immediately.
+    pub unbonding_period: u64,
+    /// Whether staked funds are delegated to the `VALIDATORS` set instead of
+    /// held by the contract.
+    pub delegate_stake: bool,
+    /// Commitments stakers can choose from when locking stake.
+    pub lock_tiers: Vec<LockTier>,
+    /// Lock expiries passed by the last index update, not stored in
+    /// `LOCK_EXPIRIES` yet. Written out by `save_config`.
+    #[serde(skip)]
+    pub passed_lock_expiries: Vec<(u64, LockExpiry)>,
+    pub early_withdrawal_penalty: EarlyWithdrawalPenalty,
+    /// Operations currently halted.
+    pub pause_flags: PauseFlags,
+    /// Address allowed to pause operations besides the owner. Unlike the
+    /// owner it cannot resume them.
+    pub guardian: Option<Addr>,
+}
+
+/// Circuit breakers, each halting one kind of operation while set.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct PauseFlags {
+    pub stake: bool,
+    /// Emergency withdrawals and releasing unbonded stake stay open.
+    pub withdraw: bool,
+    /// Covers compounding too, auto-compounding positions bank their rewards
+    /// instead and enabling it is refused.
+    pub claim: bool,
+    /// Stops every stream emitting, the time paused is not made up for.
+    pub accrual: bool,
+}
+
+/// Share of a deposit forfeited when it is withdrawn within `period` seconds
+/// of staking, `rate` right after staking and decaying linearly to nothing.
+/// Withdrawals are taken from the newest deposits first. The penalty goes to
+/// `treasury`, or when unset to the remaining stakers through the reward
+/// stream paid in the stake asset.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct EarlyWithdrawalPenalty {
+    pub rate: Decimal,
+    pub period: u64,
+    pub treasury: Option<Addr>,
+}
+
+/// Locking stake for `duration` seconds weighs it by `boost` when sharing
+/// rewards. Locks run on to the next whole day, so those ending the same day
+/// share one `LockExpiry`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LockTier {
+    pub duration: u64,
+    pub boost: Decimal,
+}
+
+/// Locks ending at the same time. The index is settled then so their boost
+/// earns nothing past it, however long their stakers take to be settled
+/// again.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct LockExpiry {
+    /// Effective stake the locks add on top of their amount.
+    pub boost: Uint128,
+    /// Each stream's `global_exchange_rate` when the locks ended, `None`
+    /// until then.
+    pub exchange_rates: Option<Vec<Decimal256>>,
+}
+
+/// A reward token emitted to stakers at its own rate, with its own index.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RewardStream {
+    /// Token the stream is paid in.
+    pub asset: AssetInfo,
+    /// Amount of `asset` emitted every 30 days, on top of any campaigns. 0
+    /// for a stream emitting through campaigns only.
+    pub monthly_reward: Uint128,
+    /// Reward emitted per second, shared between all staked tokens.
+    pub eps: Decimal256,
+    /// Budgets emitted evenly over a fixed window, in scheduling order. Moved
+    /// to `PAST_CAMPAIGNS` once ended and accrued.
+    pub campaigns: Vec<Campaign>,
+    /// Cumulative reward paid per staked token since the stream was added.
+    pub global_exchange_rate: Decimal256,
+    /// Rewards emitted while nothing was staked and not yet rolled over or
+    /// reclaimed.
+    pub undistributed_rewards: Decimal256,
+    /// Total of `asset` ever sent in with `FundRewards`.
+    pub funded: Uint128,
+    /// Total emitted so far, never more than `funded`. Emission pauses once
+    /// the two are equal.
+    pub distributed: Decimal256,
+    /// Total paid out, to stakers or reclaimed by the owner.
+    pub claimed: Uint128,
+}
+
+impl RewardStream {
+    /// A stream emitting `monthly_reward` of `asset`, starting from a zero
+    /// index and an empty reserve.
+    pub fn new(asset: AssetInfo, monthly_reward: Uint128) -> Self {
+        RewardStream {
+            asset,
+            monthly_reward,
+            eps: calculate_eps(monthly_reward),
+            campaigns: vec![],
+            global_exchange_rate: Decimal256::zero(),
+            undistributed_rewards: Decimal256::zero(),
+            funded: Uint128::zero(),
+            distributed: Decimal256::zero(),
+            claimed: Uint128::zero(),
+        }
+    }
+
+    /// Funded rewards not emitted yet.
+    pub fn reserve(&self) -> Decimal256 {
+        Decimal256::from_ratio(self.funded, 1u8).saturating_sub(self.distributed)
+    }
+}
+
+/// `budget` emitted evenly between `start` and `end`, in seconds.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Campaign {
+    pub start: u64,
+    pub end: u64,
+    pub budget: Uint128,
+}
+
+/// What happens to rewards emitted while `total_value_locked` is zero.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum UndistributedPolicy {
+    /// Added to the reward index once there are stakers again.
+    Rollover,
+    /// Kept aside for the owner to reclaim.
+    Reclaim,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct User {
-    pub staked_amount: Coin,
-    pub exchange_rate: Uint128,
+    pub staked_amount: Uint128,
+    /// `staked_amount` weighted by the boost of its locks, what rewards
+    /// accrue on.
+    pub effective_stake: Uint128,
+    /// Parts of `staked_amount` that cannot be withdrawn yet. Locks stop
+    /// boosting at `unlock_at` and are dropped when the user is next settled.
+    pub locks: Vec<Lock>,
     pub last_staked_time: u64,
+    /// Stake added while an early withdrawal penalty applies and not yet past
+    /// its period, oldest first.
+    pub deposits: Vec<Deposit>,
+    /// Settlement state per reward stream, in `Config.reward_streams` order.
+    /// Streams added since the user was last settled are missing.
+    pub rewards: Vec<UserReward>,
+    /// Whether rewards paid in the stake asset are staked whenever the user
+    /// is settled.
+    pub auto_compound: bool,
+}
+
+/// One stake, penalized on withdrawal until `period` seconds after
+/// `staked_at`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Deposit {
+    pub amount: Uint128,
+    pub staked_at: u64,
+    /// When it can first be withdrawn, its lock's `unlock_at` or `staked_at`.
+    pub unlock_at: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Lock {
+    pub amount: Uint128,
+    pub boost: Decimal,
+    pub unlock_at: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct UserReward {
+    /// Value of the stream's `global_exchange_rate` when the user was last
+    /// settled.
+    pub exchange_rate: Decimal256,
+    /// Rewards settled but not yet claimed.
     pub rewards: Uint128,
 }
 
+/// Withdrawn stake waiting out the unbonding period. It no longer earns
+/// rewards.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct UnbondingEntry {
+    pub amount: Uint128,
+    pub release_at: u64,
+}
+
+/// Contract owner allowed to run privileged operations. Ownership is handed
+/// over in two steps: the owner proposes `pending_owner`, who then accepts.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Ownership {
+    /// `None` once ownership has been renounced.
+    pub owner: Option<Addr>,
+    pub pending_owner: Option<Addr>,
+}
+
 pub const CONFIG: Item<Config> = Item::new("config");
 pub const USERS: Map<&Addr, User> = Map::new("users");
+pub const OWNERSHIP: Item<Ownership> = Item::new("ownership");
+pub const UNBONDING: Map<&Addr, Vec<UnbondingEntry>> = Map::new("unbonding");
+/// Stake delegated to each validator of the set, by operator address.
+pub const VALIDATORS: Map<&str, Uint128> = Map::new("validators");
+/// Lock expiries by unlock time, which is always a multiple of
+/// `LOCK_EXPIRY_BUCKET`.
+pub const LOCK_EXPIRIES: Map<u64, LockExpiry> = Map::new("lock_expiries");
+/// Campaigns ended and fully accrued, with the id of their stream, numbered
+/// in the order they were archived.
+pub const PAST_CAMPAIGNS: Map<u64, (u64, Campaign)> = Map::new("past_campaigns");
+/// Contract balance of the bonded denom before a harvest, kept until its
+/// reply measures what was withdrawn.
+pub const HARVEST_BALANCE: Item<Uint128> = Item::new("harvest_balance");
 
+/// Saves `config`, archiving the campaigns that ended by its last update to
+/// `PAST_CAMPAIGNS` first and storing the lock expiries it passed.
 pub fn save_config(storage: &mut dyn Storage, config: &Config) -> StdResult<()> {
-    CONFIG.save(storage, config)
+    for (unlock_at, expiry) in &config.passed_lock_expiries {
+        LOCK_EXPIRIES.save(storage, *unlock_at, expiry)?;
+    }
+    let last_update_time = config.last_update_time;
+    let ended = |campaign: &Campaign| campaign.end <= last_update_time;
+    if !config
+        .reward_streams
+        .iter()
+        .any(|stream| stream.campaigns.iter().any(ended))
+    {
+        return CONFIG.save(storage, config);
+    }
+
+    let mut next_id = PAST_CAMPAIGNS
+        .keys(storage, None, None, Order::Descending)
+        .next()
+        .transpose()?
+        .map_or(0, |id| id + 1);
+    let mut config = config.clone();
+    for (stream_id, stream) in config.reward_streams.iter_mut().enumerate() {
+        let (past, campaigns): (Vec<_>, Vec<_>) = std::mem::take(&mut stream.campaigns)
+            .into_iter()
+            .partition(ended);
+        stream.campaigns = campaigns;
+        for campaign in past {
+            PAST_CAMPAIGNS.save(storage, next_id, &(stream_id as u64, campaign))?;
+            next_id += 1;
+        }
+    }
+    CONFIG.save(storage, &config)
 }
 
 pub fn read_config(storage: &dyn Storage) -> StdResult<Config> {